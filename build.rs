@@ -1,5 +1,5 @@
 fn main() {
-    #[cfg(feature = "miyoo")]
+    #[cfg(any(feature = "miyoo", feature = "headless"))]
     slint_build::compile_with_config(
         "ui/main.slint",
         slint_build::CompilerConfiguration::new()
@@ -0,0 +1,138 @@
+use ::alsa::pcm::{Access, Format, HwParams, IoFormat, PCM};
+use ::alsa::{Direction, ValueOr};
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use nix::errno::Errno;
+use symphonia::core::conv::IntoSample;
+
+use crate::audio::output::{Output, CHANNELS, SAMPLE_RATE};
+
+/// How much audio the device buffers, in microseconds.
+const BUFFER_TIME: u32 = 200_000;
+
+/// Sample formats we can write, in order of preference.
+#[derive(Debug, Copy, Clone)]
+enum SampleFormat {
+    S16,
+    S32,
+    F32,
+}
+
+impl SampleFormat {
+    fn alsa(self) -> Format {
+        match self {
+            SampleFormat::S16 => Format::s16(),
+            SampleFormat::S32 => Format::s32(),
+            SampleFormat::F32 => Format::float(),
+        }
+    }
+}
+
+/// ALSA output.
+pub struct Alsa {
+    pcm: PCM,
+    format: SampleFormat,
+    sample_rate: u32,
+    channels: usize,
+    buffer: Vec<i16>,
+    buffer_s32: Vec<i32>,
+}
+
+impl Alsa {
+    pub fn open(device: &str) -> Result<Self> {
+        let pcm = PCM::new(device, Direction::Playback, false)
+            .with_context(|| format!("Failed to open ALSA device {device}"))?;
+
+        let (format, channels, rate) = configure(&pcm, CHANNELS as u32, SAMPLE_RATE)?;
+        debug!("opened ALSA device {device}: {format:?}, {channels} channels at {rate} Hz");
+
+        Ok(Self {
+            pcm,
+            format,
+            sample_rate: rate,
+            channels: channels as usize,
+            buffer: vec![],
+            buffer_s32: vec![],
+        })
+    }
+}
+
+impl Output for Alsa {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        // Let the device play what it has at the old rate first.
+        self.pcm.drain()?;
+        let (_, _, rate) = configure(&self.pcm, self.channels as u32, sample_rate)?;
+        debug!("switched ALSA device to {rate} Hz");
+        self.sample_rate = rate;
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        match self.format {
+            SampleFormat::S16 => {
+                self.buffer.clear();
+                self.buffer.extend(
+                    samples
+                        .iter()
+                        .map(|&sample| IntoSample::<i16>::into_sample(sample)),
+                );
+                write_all(&self.pcm, self.channels, &self.buffer)
+            }
+            SampleFormat::S32 => {
+                self.buffer_s32.clear();
+                self.buffer_s32.extend(
+                    samples
+                        .iter()
+                        .map(|&sample| IntoSample::<i32>::into_sample(sample)),
+                );
+                write_all(&self.pcm, self.channels, &self.buffer_s32)
+            }
+            SampleFormat::F32 => write_all(&self.pcm, self.channels, samples),
+        }
+    }
+}
+
+/// Writes interleaved `samples` in the device's format, blocking until it has taken them all.
+fn write_all<S: IoFormat>(pcm: &PCM, channels: usize, mut samples: &[S]) -> Result<()> {
+    let io = pcm.io_checked::<S>()?;
+    while !samples.is_empty() {
+        match io.writei(samples) {
+            Ok(frames) => samples = &samples[frames * channels..],
+            Err(e) => {
+                if e.errno() == Errno::EPIPE as i32 {
+                    warn!("ALSA underrun");
+                }
+                // Recovers from underruns and suspends by preparing the device again.
+                pcm.try_recover(e, true)
+                    .context("Failed to write to ALSA device")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets up the hardware parameters, returning the sample format, channels and rate the device
+/// settled on.
+fn configure(pcm: &PCM, channels: u32, rate: u32) -> Result<(SampleFormat, u32, u32)> {
+    let params = HwParams::any(pcm)?;
+    params.set_access(Access::RWInterleaved)?;
+    let format = [SampleFormat::S16, SampleFormat::S32, SampleFormat::F32]
+        .into_iter()
+        .find(|format| params.test_format(format.alsa()).is_ok())
+        .ok_or_else(|| anyhow!("ALSA device supports none of S16, S32 and float samples"))?;
+    params.set_format(format.alsa())?;
+    let channels = params.set_channels_near(channels)?;
+    let rate = params.set_rate_near(rate, ValueOr::Nearest)?;
+    params.set_buffer_time_near(BUFFER_TIME, ValueOr::Nearest)?;
+    pcm.hw_params(&params)
+        .context("Failed to set ALSA hardware parameters")?;
+    Ok((format, channels, rate))
+}
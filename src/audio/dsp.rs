@@ -0,0 +1,198 @@
+use log::debug;
+
+use crate::audio::eq::{Band, Equalizer};
+use crate::audio::fade::Ramp;
+use crate::audio::stereo::{Crossfeed, Stereo};
+
+/// A stage of processing between decoding and the output, working on interleaved frames at the
+/// output's sample rate and channel count.
+pub trait Dsp: Send {
+    /// Processes interleaved `samples` in place.
+    fn process(&mut self, samples: &mut [f32]);
+
+    /// Delay the stage adds, in frames.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Forgets the samples processed so far, e.g. after a seek.
+    fn reset(&mut self) {}
+}
+
+/// Time the volume takes to move between two settings, in ms, so that changes don't click.
+const VOLUME_RAMP_MS: u32 = 50;
+
+/// Settings of every stage of the chain. Stages that would do nothing are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DspSettings {
+    /// Equalizer bands, including the bass and treble shelves.
+    pub equalizer: Vec<Band>,
+    /// Gain of the side signal: 0 is mono, 1 unchanged, above 1 wider.
+    pub width: f32,
+    /// From -1, left only, to 1, right only.
+    pub balance: f32,
+    /// Crossfeed cutoff in Hz and level in dB, if enabled.
+    pub crossfeed: Option<(f32, f32)>,
+    /// Playback speed. Applied by the time-stretcher ahead of the chain, as it changes the
+    /// number of samples.
+    pub speed: f32,
+    /// Gain applied after every stage, from 0 to 1. Ramped rather than jumped to.
+    pub volume: f32,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            equalizer: vec![],
+            width: 1.0,
+            balance: 0.0,
+            crossfeed: None,
+            speed: 1.0,
+            volume: 1.0,
+        }
+    }
+}
+
+/// The stages applied in order to everything that is played.
+pub struct Chain {
+    sample_rate: u32,
+    channels: usize,
+    equalizer: Option<Equalizer>,
+    stereo: Option<Stereo>,
+    crossfeed: Option<Crossfeed>,
+    /// The settings the stages were configured with.
+    settings: Option<DspSettings>,
+    volume: Ramp,
+}
+
+impl Chain {
+    pub fn new(settings: &DspSettings, sample_rate: u32, channels: usize) -> Self {
+        let mut chain = Self {
+            sample_rate,
+            channels,
+            equalizer: None,
+            stereo: None,
+            crossfeed: None,
+            settings: None,
+            volume: Ramp::new(sample_rate, channels, VOLUME_RAMP_MS, settings.volume),
+        };
+        chain.configure(settings);
+        chain
+    }
+
+    /// Applies `settings` to the stages. Stages whose settings are unchanged keep their state, and
+    /// the others are updated in place where they can be, so that changing one setting does not
+    /// click. The speed is left to the time-stretcher.
+    pub fn configure(&mut self, settings: &DspSettings) {
+        let current = self.settings.replace(settings.clone());
+        self.volume.set(settings.volume);
+
+        let stages = self.stages().count();
+        if current.as_ref().map(|current| &current.equalizer) != Some(&settings.equalizer) {
+            if !settings.equalizer.iter().any(|band| band.gain != 0.0) {
+                self.equalizer = None;
+            } else if let Some(equalizer) = &mut self.equalizer {
+                equalizer.set_bands(&settings.equalizer);
+            } else {
+                self.equalizer = Some(Equalizer::new(
+                    &settings.equalizer,
+                    self.sample_rate,
+                    self.channels,
+                ));
+            }
+        }
+
+        let stereo = self.channels == 2;
+        // Width and balance are stateless, so they are simply replaced.
+        self.stereo = (stereo && (settings.width != 1.0 || settings.balance != 0.0))
+            .then(|| Stereo::new(settings.width, settings.balance));
+        if current.as_ref().map(|current| current.crossfeed) != Some(settings.crossfeed) {
+            self.crossfeed = settings
+                .crossfeed
+                .filter(|_| stereo)
+                .map(|(cutoff, level)| Crossfeed::new(cutoff, level, self.sample_rate));
+        }
+
+        if self.stages().count() != stages || current.is_none() {
+            debug!(
+                "{} DSP stages, {} frames of latency",
+                self.stages().count(),
+                self.latency()
+            );
+        }
+    }
+
+    /// The stages in the order they are applied.
+    fn stages(&self) -> impl Iterator<Item = &dyn Dsp> {
+        let equalizer = self.equalizer.as_ref().map(|stage| stage as &dyn Dsp);
+        let stereo = self.stereo.as_ref().map(|stage| stage as &dyn Dsp);
+        let crossfeed = self.crossfeed.as_ref().map(|stage| stage as &dyn Dsp);
+        [equalizer, stereo, crossfeed].into_iter().flatten()
+    }
+
+    fn stages_mut(&mut self) -> impl Iterator<Item = &mut dyn Dsp> {
+        let equalizer = self.equalizer.as_mut().map(|stage| stage as &mut dyn Dsp);
+        let stereo = self.stereo.as_mut().map(|stage| stage as &mut dyn Dsp);
+        let crossfeed = self.crossfeed.as_mut().map(|stage| stage as &mut dyn Dsp);
+        [equalizer, stereo, crossfeed].into_iter().flatten()
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for stage in self.stages_mut() {
+            stage.process(samples);
+        }
+        self.volume.process(samples);
+    }
+
+    /// Total delay of the stages, in frames.
+    pub fn latency(&self) -> usize {
+        self.stages().map(|stage| stage.latency()).sum()
+    }
+
+    pub fn reset(&mut self) {
+        for stage in self.stages_mut() {
+            stage.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chain, DspSettings};
+    use crate::audio::eq::Band;
+
+    #[test]
+    fn test_volume() {
+        let mut settings = DspSettings::default();
+        let mut chain = Chain::new(&settings, 1000, 1);
+
+        // The volume ramps over 50 ms, rather than jumping.
+        settings.volume = 0.0;
+        chain.configure(&settings);
+        let mut samples = [1.0; 100];
+        chain.process(&mut samples);
+        assert!((samples[24] - 0.5).abs() < 0.05);
+        assert_eq!(samples[60], 0.0);
+    }
+
+    #[test]
+    fn test_configure_in_place() {
+        let mut settings = DspSettings {
+            equalizer: vec![Band::bass(6.0)],
+            ..DspSettings::default()
+        };
+        let mut chain = Chain::new(&settings, 44100, 1);
+        let mut reference = Chain::new(&settings, 44100, 1);
+        let (mut samples, mut expected) = ([0.5; 64], [0.5; 64]);
+        chain.process(&mut samples);
+        reference.process(&mut expected);
+
+        // A change of speed leaves the filters as they are, mid-stream.
+        settings.speed = 2.0;
+        chain.configure(&settings);
+        let (mut samples, mut expected) = ([0.5; 64], [0.5; 64]);
+        chain.process(&mut samples);
+        reference.process(&mut expected);
+        assert_eq!(samples, expected);
+    }
+}
@@ -0,0 +1,251 @@
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::dsp::Dsp;
+
+/// Gain of the bass and treble shortcuts, and of each band, is limited to this many dB either way.
+pub const MAX_GAIN: f32 = 12.0;
+
+/// Corner frequency of the bass shelf, in Hz.
+const BASS_FREQUENCY: f32 = 120.0;
+/// Corner frequency of the treble shelf, in Hz.
+const TREBLE_FREQUENCY: f32 = 8000.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    /// Boosts or cuts around `frequency`, `q` controlling how narrowly.
+    Peak,
+    /// Boosts or cuts everything below `frequency`.
+    LowShelf,
+    /// Boosts or cuts everything above `frequency`.
+    HighShelf,
+}
+
+/// One band of the parametric equalizer.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Band {
+    pub kind: FilterKind,
+    /// Center or corner frequency, in Hz.
+    pub frequency: f32,
+    /// Gain in dB.
+    pub gain: f32,
+    #[serde(default = "default_q")]
+    pub q: f32,
+}
+
+fn default_q() -> f32 {
+    1.0
+}
+
+impl Band {
+    pub fn peak(frequency: f32, gain: f32) -> Self {
+        Self {
+            kind: FilterKind::Peak,
+            frequency,
+            gain,
+            q: default_q(),
+        }
+    }
+
+    /// The bass shortcut, a low shelf.
+    pub fn bass(gain: f32) -> Self {
+        Self {
+            kind: FilterKind::LowShelf,
+            frequency: BASS_FREQUENCY,
+            gain,
+            q: FRAC_1_SQRT_2,
+        }
+    }
+
+    /// The treble shortcut, a high shelf.
+    pub fn treble(gain: f32) -> Self {
+        Self {
+            kind: FilterKind::HighShelf,
+            frequency: TREBLE_FREQUENCY,
+            gain,
+            q: FRAC_1_SQRT_2,
+        }
+    }
+}
+
+/// A named set of bands.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub bands: Vec<Band>,
+}
+
+/// The presets that ship with vinyl. User presets from the config come after these.
+pub fn builtin_presets() -> Vec<Preset> {
+    const FREQUENCIES: [f32; 5] = [60.0, 250.0, 1000.0, 4000.0, 12000.0];
+    let preset = |name: &str, gains: [f32; 5]| Preset {
+        name: name.to_string(),
+        bands: FREQUENCIES
+            .iter()
+            .zip(gains)
+            .map(|(&frequency, gain)| Band::peak(frequency, gain))
+            .collect(),
+    };
+
+    vec![
+        preset("Flat", [0.0, 0.0, 0.0, 0.0, 0.0]),
+        preset("Bass Boost", [6.0, 3.0, 0.0, 0.0, 0.0]),
+        // Makes up for the missing low end and air of cheap earbuds.
+        preset("Earbuds", [5.0, 2.0, 0.0, -1.0, 2.0]),
+        preset("Vocal", [-2.0, -1.0, 3.0, 2.0, 0.0]),
+        preset("Treble Boost", [0.0, 0.0, 0.0, 3.0, 5.0]),
+    ]
+}
+
+/// A second order IIR filter, with coefficients from the Audio EQ Cookbook.
+#[derive(Debug, Clone)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    fn new(band: &Band, sample_rate: u32) -> Self {
+        let frequency = band.frequency.clamp(10.0, sample_rate as f32 * 0.45);
+        let a = 10f32.powf(band.gain / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(0.01));
+        let beta = 2.0 * a.sqrt() * alpha;
+
+        let [b0, b1, b2, a0, a1, a2] = match band.kind {
+            FilterKind::Peak => [
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ],
+            FilterKind::LowShelf => [
+                a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - beta),
+                (a + 1.0) + (a - 1.0) * cos + beta,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - beta,
+            ],
+            FilterKind::HighShelf => [
+                a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - beta),
+                (a + 1.0) - (a - 1.0) * cos + beta,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - beta,
+            ],
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Filters one sample, with `state` holding the delay line of its channel.
+    #[inline]
+    fn process(&self, x: f32, state: &mut [f32; 2]) -> f32 {
+        let y = self.b0 * x + state[0];
+        state[0] = self.b1 * x - self.a1 * y + state[1];
+        state[1] = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// Parametric equalizer over interleaved samples.
+pub struct Equalizer {
+    sample_rate: u32,
+    channels: usize,
+    filters: Vec<Biquad>,
+    /// Delay line of each filter for each channel, filter by filter.
+    state: Vec<[f32; 2]>,
+    /// Linear gain applied before filtering, so that boosts cannot clip.
+    preamp: f32,
+}
+
+impl Equalizer {
+    pub fn new(bands: &[Band], sample_rate: u32, channels: usize) -> Self {
+        let mut equalizer = Self {
+            sample_rate,
+            channels,
+            filters: vec![],
+            state: vec![],
+            preamp: 1.0,
+        };
+        equalizer.set_bands(bands);
+        equalizer
+    }
+
+    /// Replaces the bands. Bands without gain are left out. The delay lines are kept, so that
+    /// adjusting a band while playing does not click.
+    pub fn set_bands(&mut self, bands: &[Band]) {
+        self.filters = bands
+            .iter()
+            .filter(|band| band.gain != 0.0)
+            .map(|band| Biquad::new(band, self.sample_rate))
+            .collect();
+        self.state
+            .resize(self.filters.len() * self.channels, [0.0; 2]);
+
+        // Lower the level by the largest boost.
+        let boost = bands.iter().map(|band| band.gain).fold(0.0, f32::max);
+        self.preamp = 10f32.powf(-boost / 20.0);
+    }
+}
+
+impl Dsp for Equalizer {
+    fn process(&mut self, samples: &mut [f32]) {
+        if self.filters.is_empty() {
+            return;
+        }
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let mut x = *sample * self.preamp;
+                for (i, filter) in self.filters.iter().enumerate() {
+                    x = filter.process(x, &mut self.state[i * self.channels + channel]);
+                }
+                *sample = x;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.iter_mut().for_each(|state| *state = [0.0; 2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Band, Biquad, Equalizer};
+    use crate::audio::dsp::Dsp;
+
+    #[test]
+    fn test_equalizer() {
+        // Without gain, samples pass through untouched.
+        let mut samples = [0.1, -0.2, 0.3, -0.4];
+        Equalizer::new(&[Band::peak(1000.0, 0.0), Band::bass(0.0)], 44100, 2).process(&mut samples);
+        assert_eq!(samples, [0.1, -0.2, 0.3, -0.4]);
+
+        // A bass shelf of +6 dB doubles DC, a treble shelf leaves it alone.
+        let dc = |band: Band| {
+            let filter = Biquad::new(&band, 44100);
+            let mut state = [0.0; 2];
+            (0..10000).fold(0.0, |_, _| filter.process(1.0, &mut state))
+        };
+        assert!((dc(Band::bass(6.0)) - 1.995).abs() < 1e-2);
+        assert!((dc(Band::treble(6.0)) - 1.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,75 @@
+/// A gain that moves towards a target at a fixed rate, one step per frame, so that changes of
+/// volume and starting or stopping mid-song don't click.
+pub struct Ramp {
+    channels: usize,
+    /// Change of gain per frame.
+    step: f32,
+    gain: f32,
+    target: f32,
+}
+
+impl Ramp {
+    /// A ramp that takes `ms` to go from silence to full volume, starting at `gain`.
+    pub fn new(sample_rate: u32, channels: usize, ms: u32, gain: f32) -> Self {
+        Self {
+            channels,
+            step: 1000.0 / (ms as f32 * sample_rate as f32),
+            gain,
+            target: gain,
+        }
+    }
+
+    /// Starts moving towards `target`. With a ramp of 0 ms, it is reached at once.
+    pub fn set(&mut self, target: f32) {
+        self.target = target;
+        if self.step.is_infinite() {
+            self.gain = target;
+        }
+    }
+
+    /// Whether the ramp has gone, and stays, silent.
+    pub fn is_silent(&self) -> bool {
+        self.gain == 0.0 && self.target == 0.0
+    }
+
+    /// Applies the gain to interleaved `samples`, moving it along.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.gain == 1.0 && self.target == 1.0 {
+            return;
+        }
+        for frame in samples.chunks_exact_mut(self.channels) {
+            self.gain = if self.gain < self.target {
+                (self.gain + self.step).min(self.target)
+            } else {
+                (self.gain - self.step).max(self.target)
+            };
+            for sample in frame {
+                *sample *= self.gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ramp;
+
+    #[test]
+    fn test_ramp() {
+        let mut ramp = Ramp::new(1000, 2, 10, 1.0);
+        ramp.set(0.0);
+        let mut samples = [1.0; 40];
+        ramp.process(&mut samples);
+        assert_eq!(samples[0], samples[1]);
+        assert!(samples[16] > 0.0 && samples[20] == 0.0);
+        assert!(ramp.is_silent());
+
+        ramp.set(1.0);
+        assert!(!ramp.is_silent());
+
+        // Without a ramp the gain jumps.
+        let mut ramp = Ramp::new(1000, 1, 0, 1.0);
+        ramp.set(0.0);
+        assert!(ramp.is_silent());
+    }
+}
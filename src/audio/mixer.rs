@@ -0,0 +1,234 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use symphonia::core::audio::{Channels, Position as ChannelPosition};
+
+/// Speaker position of a channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Position {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    Lfe,
+    FrontLeftCenter,
+    FrontRightCenter,
+    BackLeft,
+    BackRight,
+    BackCenter,
+    SideLeft,
+    SideRight,
+}
+
+use Position::*;
+
+/// The positions we mix, by the symphonia channel they come from.
+const POSITIONS: [(ChannelPosition, Position); 11] = [
+    (ChannelPosition::FRONT_LEFT, FrontLeft),
+    (ChannelPosition::FRONT_RIGHT, FrontRight),
+    (ChannelPosition::FRONT_CENTER, FrontCenter),
+    (ChannelPosition::LFE1, Lfe),
+    (ChannelPosition::REAR_LEFT, BackLeft),
+    (ChannelPosition::REAR_RIGHT, BackRight),
+    (ChannelPosition::FRONT_LEFT_CENTER, FrontLeftCenter),
+    (ChannelPosition::FRONT_RIGHT_CENTER, FrontRightCenter),
+    (ChannelPosition::REAR_CENTER, BackCenter),
+    (ChannelPosition::SIDE_LEFT, SideLeft),
+    (ChannelPosition::SIDE_RIGHT, SideRight),
+];
+
+/// Channel layout of a track, in the order symphonia decodes the channels. Returns `None` if
+/// the track does not say where its channels go, or has one we don't mix.
+fn positions(channels: &Channels) -> Option<Vec<Position>> {
+    let Channels::Positioned(mask) = channels else {
+        return None;
+    };
+    mask.iter()
+        .map(|channel| {
+            POSITIONS
+                .iter()
+                .find(|&&(c, _)| c == channel)
+                .map(|&(_, position)| position)
+        })
+        .collect()
+}
+
+/// Channel layout for a number of channels, in the order symphonia decodes them. Returns `None`
+/// for channel counts without a standard layout.
+fn layout(channels: usize) -> Option<&'static [Position]> {
+    Some(match channels {
+        1 => &[FrontCenter],
+        2 => &[FrontLeft, FrontRight],
+        3 => &[FrontLeft, FrontRight, FrontCenter],
+        4 => &[FrontLeft, FrontRight, BackLeft, BackRight],
+        5 => &[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight],
+        6 => &[FrontLeft, FrontRight, FrontCenter, Lfe, BackLeft, BackRight],
+        7 => &[
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            Lfe,
+            BackCenter,
+            SideLeft,
+            SideRight,
+        ],
+        8 => &[
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            Lfe,
+            BackLeft,
+            BackRight,
+            SideLeft,
+            SideRight,
+        ],
+        _ => return None,
+    })
+}
+
+/// Where a channel missing from the output is folded into, in order of preference, with the
+/// ITU-R BS.775 downmix coefficient. The LFE channel is dropped.
+fn fold(position: Position) -> &'static [&'static [(Position, f32)]] {
+    const C: f32 = FRAC_1_SQRT_2;
+    match position {
+        FrontLeft => &[&[(FrontCenter, 1.0)]],
+        FrontRight => &[&[(FrontCenter, 1.0)]],
+        FrontCenter => &[&[(FrontLeft, C), (FrontRight, C)]],
+        Lfe => &[],
+        FrontLeftCenter => &[&[(FrontLeft, C), (FrontCenter, C)], &[(FrontLeft, 1.0)]],
+        FrontRightCenter => &[&[(FrontRight, C), (FrontCenter, C)], &[(FrontRight, 1.0)]],
+        BackLeft => &[&[(SideLeft, 1.0)], &[(FrontLeft, C)], &[(FrontCenter, C)]],
+        BackRight => &[&[(SideRight, 1.0)], &[(FrontRight, C)], &[(FrontCenter, C)]],
+        SideLeft => &[&[(BackLeft, 1.0)], &[(FrontLeft, C)], &[(FrontCenter, C)]],
+        SideRight => &[&[(BackRight, 1.0)], &[(FrontRight, C)], &[(FrontCenter, C)]],
+        BackCenter => &[
+            &[(BackLeft, C), (BackRight, C)],
+            &[(SideLeft, C), (SideRight, C)],
+            &[(FrontLeft, C), (FrontRight, C)],
+            &[(FrontCenter, C)],
+        ],
+    }
+}
+
+/// Maps interleaved frames from the channels of a track to the channels of an output, with a
+/// mixing matrix built from their channel layouts.
+pub struct Mixer {
+    inputs: usize,
+    /// Gain of each input channel in each output channel, row by row.
+    matrix: Option<Vec<f32>>,
+}
+
+impl Mixer {
+    /// A mixer from a track's `inputs` channels to `outputs` channels. The track's layout is
+    /// guessed from the number of channels if `channels` does not give their positions.
+    pub fn new(inputs: usize, channels: Option<&Channels>, outputs: usize) -> Self {
+        let from = channels
+            .and_then(positions)
+            .filter(|from| from.len() == inputs)
+            .or_else(|| layout(inputs).map(<[_]>::to_vec));
+        let matrix = (inputs != outputs || from.as_deref() != layout(outputs))
+            .then(|| Self::matrix(inputs, from.as_deref(), outputs));
+        Self { inputs, matrix }
+    }
+
+    fn matrix(inputs: usize, from: Option<&[Position]>, outputs: usize) -> Vec<f32> {
+        let mut matrix = vec![0.0; inputs * outputs];
+        let (Some(from), Some(to)) = (from, layout(outputs)) else {
+            // Without a layout, map the channels in order and drop or silence the rest.
+            for i in 0..inputs.min(outputs) {
+                matrix[i * inputs + i] = 1.0;
+            }
+            return matrix;
+        };
+
+        let index = |position| to.iter().position(|&p| p == position);
+        for (i, &position) in from.iter().enumerate() {
+            if inputs == 1 {
+                // Play mono on both front speakers at full level, or on the center one if
+                // that's all there is.
+                let mut targets: Vec<_> = [FrontLeft, FrontRight]
+                    .into_iter()
+                    .filter_map(index)
+                    .collect();
+                if targets.is_empty() {
+                    targets.extend(index(FrontCenter));
+                }
+                for o in targets {
+                    matrix[o * inputs + i] = 1.0;
+                }
+            } else if let Some(o) = index(position) {
+                matrix[o * inputs + i] = 1.0;
+            } else if let Some(targets) = fold(position)
+                .iter()
+                .find(|targets| targets.iter().all(|&(p, _)| index(p).is_some()))
+            {
+                for &(target, gain) in targets.iter() {
+                    matrix[index(target).unwrap() * inputs + i] += gain;
+                }
+            }
+        }
+
+        // Scale down rows that add up to more than unity, so the downmix cannot clip.
+        for row in matrix.chunks_exact_mut(inputs) {
+            let sum: f32 = row.iter().sum();
+            if sum > 1.0 {
+                row.iter_mut().for_each(|gain| *gain /= sum);
+            }
+        }
+        matrix
+    }
+
+    /// Mixes `src` into `dst`, replacing its contents.
+    pub fn mix(&self, src: &[f32], dst: &mut Vec<f32>) {
+        dst.clear();
+        let Some(ref matrix) = self.matrix else {
+            dst.extend_from_slice(src);
+            return;
+        };
+
+        for frame in src.chunks_exact(self.inputs) {
+            dst.extend(matrix.chunks_exact(self.inputs).map(|row| {
+                row.iter()
+                    .zip(frame)
+                    .map(|(gain, sample)| gain * sample)
+                    .sum::<f32>()
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use symphonia::core::audio::{Channels, Position};
+
+    use super::Mixer;
+
+    fn mix(inputs: usize, outputs: usize, src: &[f32]) -> Vec<f32> {
+        let mut dst = vec![];
+        Mixer::new(inputs, None, outputs).mix(src, &mut dst);
+        dst
+    }
+
+    #[test]
+    fn test_mix() {
+        assert_eq!(mix(2, 2, &[0.1, 0.2, 0.3, 0.4]), [0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(mix(1, 2, &[0.5, -0.5]), [0.5, 0.5, -0.5, -0.5]);
+        assert_eq!(mix(2, 1, &[1.0, 0.0]), [0.5]);
+
+        // 5.1 to stereo: the center and surrounds are folded in at -3 dB, the LFE dropped.
+        let sum = 1.0 + 2.0 * FRAC_1_SQRT_2;
+        let left = mix(6, 2, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert!((left[0] - 1.0 / sum).abs() < 1e-6 && left[1] == 0.0);
+        let center = mix(6, 2, &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+        assert!((center[0] - FRAC_1_SQRT_2 / sum).abs() < 1e-6 && center[0] == center[1]);
+        assert_eq!(mix(6, 2, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]), [0.0, 0.0]);
+
+        // Four channels that are not quad: the third is the center, the fourth the LFE.
+        let layout = Channels::Positioned(
+            Position::FRONT_LEFT | Position::FRONT_RIGHT | Position::FRONT_CENTER | Position::LFE1,
+        );
+        let mut dst = vec![];
+        Mixer::new(4, Some(&layout), 2).mix(&[0.0, 0.0, 1.0, 1.0], &mut dst);
+        assert!((dst[0] - FRAC_1_SQRT_2 / (1.0 + FRAC_1_SQRT_2)).abs() < 1e-6 && dst[0] == dst[1]);
+    }
+}
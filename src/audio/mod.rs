@@ -1,21 +1,135 @@
-use std::path::Path;
-use std::sync::LazyLock;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use log::{debug, info};
 
-#[cfg(feature = "miyoo")]
-mod oss;
-#[cfg(feature = "miyoo")]
-pub static AUDIO: LazyLock<oss::Oss> = LazyLock::new(|| oss::Oss::new());
+use crate::audio::dsp::DspSettings;
+use crate::audio::output::Output;
+use crate::audio::pipeline::Pipeline;
+use crate::config::AudioConfig;
 
-#[cfg(feature = "simulator")]
+#[cfg(feature = "alsa")]
+mod alsa;
+pub mod dsp;
+pub mod eq;
+mod fade;
+mod mixer;
+mod null;
+mod oss;
+mod output;
+mod pipeline;
+mod resampler;
+#[cfg(feature = "rodio")]
 mod rodio;
-#[cfg(feature = "simulator")]
-pub static AUDIO: LazyLock<rodio::Rodio> = LazyLock::new(|| rodio::Rodio::new().unwrap());
+mod stereo;
+mod stream;
+pub mod stretch;
+mod track;
+mod wav;
+
+/// Something that happened during playback, reported by [`Audio::events`].
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The song played to the end.
+    Finished,
+    /// The song could not be loaded or decoded, e.g. because the file is corrupt.
+    Failed(String),
+    /// The output failed. Playback is paused.
+    Error(String),
+    /// A seek finished, at this position.
+    Seeked(Duration),
+}
 
 pub trait Audio {
     fn load(&self, path: &Path) -> Result<()>;
     fn play(&self) -> Result<()>;
     fn pause(&self) -> Result<()>;
-    fn seek(&self, timestamp: i32) -> Result<()>;
+    /// Seeks the current song to `position`. Where it lands is reported by [`Event::Seeked`].
+    fn seek(&self, position: Duration) -> Result<()>;
+    /// Replaces the settings of the DSP chain, taking effect immediately.
+    fn set_dsp(&self, settings: DspSettings) -> Result<()>;
+    /// Stops playback and releases the output device. The backend is unusable afterwards.
+    fn stop(&self) -> Result<()>;
+    /// Receives the playback events of this backend.
+    fn events(&self) -> kanal::Receiver<Event>;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// OSS through `/dev/dsp`.
+    Oss,
+    /// ALSA, if built with the `alsa` feature.
+    Alsa,
+    /// The desktop sound card through rodio, if built with the `simulator` feature.
+    Rodio,
+    /// Discards samples, at `--audio-speed` times real time.
+    Null,
+    /// Writes the samples to the WAV file at `--audio-file`.
+    File,
+}
+
+/// Backends tried in order when none is chosen.
+const AUTODETECT: &[Backend] = &[
+    #[cfg(feature = "rodio")]
+    Backend::Rodio,
+    Backend::Oss,
+    #[cfg(feature = "alsa")]
+    Backend::Alsa,
+];
+
+#[derive(Debug, Clone, Args)]
+pub struct AudioArgs {
+    /// Audio backend. Tries the sound devices in turn if unset.
+    #[arg(long, value_enum)]
+    pub audio: Option<Backend>,
+    /// ALSA device to play to.
+    #[arg(long, default_value = "default")]
+    pub alsa_device: String,
+    /// WAV file to write to with `--audio file`.
+    #[arg(long, default_value = "vinyl.wav")]
+    pub audio_file: PathBuf,
+    /// Playback speed relative to real time with `--audio null`, 0 for as fast as possible.
+    #[arg(long, default_value_t = 1.0)]
+    pub audio_speed: f64,
+}
+
+/// Opens the chosen backend, or the first sound device that works.
+pub fn open(args: &AudioArgs, config: &AudioConfig) -> Result<Box<dyn Audio>> {
+    if let Some(backend) = args.audio {
+        return open_backend(backend, args, config);
+    }
+
+    for &backend in AUTODETECT {
+        match open_backend(backend, args, config) {
+            Ok(audio) => return Ok(audio),
+            Err(e) => debug!("{backend:?} audio unavailable: {e:#}"),
+        }
+    }
+    Err(anyhow!("No audio device available"))
+}
+
+fn open_backend(
+    backend: Backend,
+    args: &AudioArgs,
+    config: &AudioConfig,
+) -> Result<Box<dyn Audio>> {
+    let audio: Box<dyn Audio> = match backend {
+        Backend::Oss => pipeline(oss::Dsp::open()?, config),
+        #[cfg(feature = "alsa")]
+        Backend::Alsa => pipeline(alsa::Alsa::open(&args.alsa_device)?, config),
+        #[cfg(feature = "rodio")]
+        Backend::Rodio => Box::new(rodio::Rodio::new(config)?),
+        Backend::Null => pipeline(null::Null::new(args.audio_speed), config),
+        Backend::File => pipeline(wav::Wav::create(&args.audio_file)?, config),
+        #[allow(unreachable_patterns)]
+        _ => return Err(anyhow!("vinyl was built without {backend:?} audio support")),
+    };
+    info!("using {backend:?} audio");
+    Ok(audio)
+}
+
+fn pipeline(output: impl Output + 'static, config: &AudioConfig) -> Box<dyn Audio> {
+    Box::new(Pipeline::new(Box::new(output), config.clone()))
 }
@@ -0,0 +1,60 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::audio::output::{Output, CHANNELS, SAMPLE_RATE};
+
+/// Discards samples, taking as long as a real device would to play them.
+pub struct Null {
+    /// Playback speed relative to real time. Zero or less discards samples as fast as possible.
+    speed: f64,
+    sample_rate: u32,
+    started: Instant,
+    /// Time taken by the samples written so far.
+    played: Duration,
+}
+
+impl Null {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            speed,
+            sample_rate: SAMPLE_RATE,
+            started: Instant::now(),
+            played: Duration::ZERO,
+        }
+    }
+}
+
+impl Output for Null {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        CHANNELS
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        self.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        if self.speed <= 0.0 {
+            return Ok(());
+        }
+
+        let frames = (samples.len() / CHANNELS) as f64;
+        self.played += Duration::from_secs_f64(frames / self.sample_rate as f64 / self.speed);
+        match self.played.checked_sub(self.started.elapsed()) {
+            Some(ahead) => thread::sleep(ahead),
+            // Fell behind, e.g. while paused: carry on from now instead of catching up.
+            None => {
+                let now = Instant::now();
+                self.started = now.checked_sub(self.played).unwrap_or(now);
+            }
+        }
+        Ok(())
+    }
+}
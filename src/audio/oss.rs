@@ -0,0 +1,123 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use nix::{ioctl_none, ioctl_readwrite};
+use symphonia::core::conv::IntoSample;
+
+use crate::audio::output::{Output, CHANNELS, SAMPLE_RATE};
+
+ioctl_none!(dsp_sync, b'P', 1);
+ioctl_readwrite!(dsp_speed, b'P', 2, i32);
+ioctl_readwrite!(dsp_setfmt, b'P', 5, i32);
+ioctl_readwrite!(dsp_channels, b'P', 6, i32);
+
+const AFMT_U8: i32 = 0x08;
+const AFMT_S16_LE: i32 = 0x10;
+const AFMT_S32_LE: i32 = 0x1000;
+
+#[derive(Debug, Copy, Clone)]
+enum SampleFormat {
+    U8,
+    S16,
+    S32,
+}
+
+/// OSS output through `/dev/dsp`.
+pub struct Dsp {
+    file: File,
+    format: SampleFormat,
+    sample_rate: u32,
+    channels: usize,
+    buffer: Vec<u8>,
+}
+
+impl Dsp {
+    pub fn open() -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open("/dev/dsp")
+            .context("Failed to open /dev/dsp")?;
+
+        // The driver writes back the closest values it supports. OSS requires the format to be
+        // set before the channels, and the channels before the rate.
+        let mut format = AFMT_S16_LE;
+        let mut channels = CHANNELS as i32;
+        let mut rate = SAMPLE_RATE as i32;
+        unsafe {
+            dsp_setfmt(file.as_raw_fd(), &mut format)?;
+            dsp_channels(file.as_raw_fd(), &mut channels)?;
+            dsp_speed(file.as_raw_fd(), &mut rate)?;
+        }
+
+        let format = match format {
+            AFMT_U8 => SampleFormat::U8,
+            AFMT_S16_LE => SampleFormat::S16,
+            AFMT_S32_LE => SampleFormat::S32,
+            format => return Err(anyhow!("Unsupported OSS sample format {format:#x}")),
+        };
+        if channels < 1 || rate < 1 {
+            return Err(anyhow!(
+                "Invalid OSS parameters: {channels} channels at {rate} Hz"
+            ));
+        }
+        debug!("opened /dev/dsp: {format:?}, {channels} channels at {rate} Hz");
+
+        Ok(Self {
+            file,
+            format,
+            sample_rate: rate as u32,
+            channels: channels as usize,
+            buffer: vec![],
+        })
+    }
+}
+
+impl Output for Dsp {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        // Let the device play what it has at the old rate first.
+        let mut rate = sample_rate as i32;
+        unsafe {
+            dsp_sync(self.file.as_raw_fd())?;
+            dsp_speed(self.file.as_raw_fd(), &mut rate)?;
+        }
+        debug!("switched /dev/dsp to {rate} Hz");
+        self.sample_rate = rate as u32;
+        Ok(())
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.buffer.clear();
+        match self.format {
+            SampleFormat::U8 => self.buffer.extend(
+                samples
+                    .iter()
+                    .map(|&sample| IntoSample::<u8>::into_sample(sample)),
+            ),
+            SampleFormat::S16 => {
+                for &sample in samples {
+                    let sample: i16 = sample.into_sample();
+                    self.buffer.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+            SampleFormat::S32 => {
+                for &sample in samples {
+                    let sample: i32 = sample.into_sample();
+                    self.buffer.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+        self.file.write_all(&self.buffer)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::{anyhow, Result};
+
+/// Sample rate requested from outputs.
+pub const SAMPLE_RATE: u32 = 44100;
+/// Number of interleaved channels requested from outputs.
+pub const CHANNELS: usize = 2;
+
+/// Where decoded samples end up: a sound device, or a stand-in for one.
+pub trait Output: Send {
+    /// Sample rate the output plays at, which may differ from the one requested.
+    fn sample_rate(&self) -> u32;
+    /// Number of interleaved channels the output expects.
+    fn channels(&self) -> usize;
+    /// Asks the output to play at another sample rate, once the samples written so far have
+    /// been played. The output may pick a different rate, see [`Output::sample_rate`].
+    fn set_sample_rate(&mut self, _sample_rate: u32) -> Result<()> {
+        Err(anyhow!("Changing the sample rate is not supported"))
+    }
+    /// Writes interleaved samples, blocking until the output can take them.
+    fn write(&mut self, samples: &[f32]) -> Result<()>;
+}
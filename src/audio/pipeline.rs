@@ -0,0 +1,305 @@
+use anyhow::{Context, Result};
+use log::{debug, error, warn};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::audio::dsp::DspSettings;
+use crate::audio::output::Output;
+use crate::audio::stream::Stream;
+use crate::audio::{Audio, Event};
+use crate::config::AudioConfig;
+
+enum Message {
+    Load(PathBuf),
+    Play,
+    Pause,
+    Seek(Duration),
+    SetDsp(DspSettings),
+    Stop,
+}
+
+/// Decodes songs on a worker thread and writes the samples to an [`Output`].
+pub struct Pipeline {
+    sender: kanal::Sender<Message>,
+    events: kanal::Receiver<Event>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Pipeline {
+    /// Spawns the worker thread, which writes to `output` until stopped.
+    pub fn new(output: Box<dyn Output>, config: AudioConfig) -> Self {
+        let (tx, rx) = kanal::unbounded();
+        let (events_tx, events_rx) = kanal::unbounded();
+
+        let handle = std::thread::spawn(move || {
+            Worker {
+                output,
+                config,
+                events: events_tx,
+                stream: None,
+                is_playing: true,
+                dsp: DspSettings::default(),
+                pending: None,
+                pending_seek: None,
+            }
+            .run(rx)
+        });
+
+        Self {
+            sender: tx,
+            events: events_rx,
+            handle: Mutex::new(Some(handle)),
+        }
+    }
+}
+
+/// The state of the worker thread. It plays while it has a stream and is not paused, and
+/// otherwise blocks until the next message.
+struct Worker {
+    output: Box<dyn Output>,
+    config: AudioConfig,
+    events: kanal::Sender<Event>,
+    stream: Option<Stream>,
+    is_playing: bool,
+    dsp: DspSettings,
+    /// A pause or stop that waits for the stream to fade out.
+    pending: Option<Message>,
+    /// A seek that waits for the stream to fade out, after which it fades back in.
+    pending_seek: Option<Duration>,
+}
+
+impl Worker {
+    fn run(mut self, rx: kanal::Receiver<Message>) {
+        loop {
+            let msg = if self.is_playing && self.stream.is_some() {
+                rx.try_recv()
+            } else {
+                rx.recv().map(Some)
+            };
+
+            match msg {
+                Ok(Some(msg)) => {
+                    if !self.handle(msg) {
+                        break;
+                    }
+                }
+                Ok(None) => {
+                    self.play();
+                    if !self.settle() {
+                        break;
+                    }
+                }
+                Err(_) => {
+                    debug!("audio channel closed");
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Handles a message, returning `false` once the worker should stop.
+    fn handle(&mut self, msg: Message) -> bool {
+        match msg {
+            Message::Load(path) => {
+                debug!("load {}", path.to_string_lossy());
+                // The old song was fading out; a seek in it no longer matters.
+                self.pending_seek = None;
+                match self.pending.take() {
+                    Some(Message::Pause) => self.is_playing = false,
+                    Some(Message::Stop) => return false,
+                    _ => {}
+                }
+                self.stream = match Stream::open(
+                    &path,
+                    self.output.as_mut(),
+                    &self.config,
+                    &self.dsp,
+                    self.is_playing,
+                ) {
+                    Ok(stream) => Some(stream),
+                    Err(e) => {
+                        error!("Failed to load song: {e:#}");
+                        self.send(Event::Failed(format!("{e:#}")));
+                        None
+                    }
+                };
+            }
+            Message::Play => {
+                debug!("play");
+                if let Some(Message::Pause) = self.pending {
+                    self.pending = None;
+                }
+                self.is_playing = true;
+                if let Some(ref mut stream) = self.stream {
+                    if self.pending_seek.is_none() {
+                        stream.fade_in();
+                    }
+                }
+            }
+            Message::Pause if self.is_audible() => {
+                debug!("pause, fading out");
+                self.fade_out();
+                self.pending = Some(Message::Pause);
+            }
+            Message::Pause => {
+                debug!("pause");
+                self.is_playing = false;
+            }
+            Message::Seek(position) if self.is_audible() => {
+                debug!("seek {position:?}, fading out");
+                self.fade_out();
+                self.pending_seek = Some(position);
+            }
+            Message::Seek(position) => {
+                debug!("seek {position:?}");
+                self.seek(position);
+            }
+            Message::SetDsp(settings) => {
+                debug!("dsp {settings:?}");
+                if let Some(ref mut stream) = self.stream {
+                    stream.set_dsp(&settings);
+                }
+                self.dsp = settings;
+            }
+            Message::Stop if self.is_audible() => {
+                debug!("stop, fading out");
+                self.fade_out();
+                self.pending = Some(Message::Stop);
+            }
+            Message::Stop => {
+                debug!("stop");
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether stopping the output now would cut the stream off mid-song.
+    fn is_audible(&self) -> bool {
+        self.is_playing && self.stream.is_some()
+    }
+
+    fn fade_out(&mut self) {
+        if let Some(ref mut stream) = self.stream {
+            stream.fade_out();
+        }
+    }
+
+    /// Carries out the pending seek, pause or stop once the stream has faded out, or can no
+    /// longer be heard. Returns `false` once the worker should stop.
+    fn settle(&mut self) -> bool {
+        if self.pending.is_none() && self.pending_seek.is_none() {
+            return true;
+        }
+        let faded_out = self.stream.as_ref().map_or(true, Stream::is_faded_out);
+        if self.is_playing && !faded_out {
+            return true;
+        }
+
+        if let Some(position) = self.pending_seek.take() {
+            self.seek(position);
+        }
+        match self.pending.take() {
+            Some(Message::Pause) => self.is_playing = false,
+            Some(Message::Stop) => return false,
+            _ => {
+                if let Some(ref mut stream) = self.stream {
+                    stream.fade_in();
+                }
+            }
+        }
+        true
+    }
+
+    fn seek(&mut self, position: Duration) {
+        if let Some(ref mut stream) = self.stream {
+            match stream.seek(position) {
+                Ok(position) => self.send(Event::Seeked(position)),
+                Err(e) => warn!("Failed to seek: {e:#}"),
+            }
+        }
+    }
+
+    /// Writes the next samples of the stream to the output.
+    fn play(&mut self) {
+        let Some(ref mut stream) = self.stream else {
+            return;
+        };
+
+        match stream.next() {
+            Ok(Some(samples)) => {
+                if let Err(e) = self.output.write(samples) {
+                    error!("Failed to write samples: {e:#}");
+                    self.is_playing = false;
+                    self.send(Event::Error(format!("{e:#}")));
+                }
+            }
+            Ok(None) => {
+                debug!("end of song");
+                self.stream = None;
+                self.send(Event::Finished);
+            }
+            Err(e) => {
+                error!("Failed to decode song: {e:#}");
+                self.stream = None;
+                self.send(Event::Failed(format!("{e:#}")));
+            }
+        }
+    }
+
+    fn send(&self, event: Event) {
+        if self.events.send(event).is_err() {
+            debug!("audio events dropped, nobody is listening");
+        }
+    }
+}
+
+impl Audio for Pipeline {
+    fn load(&self, path: &Path) -> Result<()> {
+        self.sender
+            .send(Message::Load(path.to_path_buf()))
+            .context("Failed to send message")
+    }
+
+    fn play(&self) -> Result<()> {
+        self.sender
+            .send(Message::Play)
+            .context("Failed to send message")
+    }
+
+    fn pause(&self) -> Result<()> {
+        self.sender
+            .send(Message::Pause)
+            .context("Failed to send message")
+    }
+
+    fn seek(&self, position: Duration) -> Result<()> {
+        self.sender
+            .send(Message::Seek(position))
+            .context("Failed to send message")
+    }
+
+    fn set_dsp(&self, settings: DspSettings) -> Result<()> {
+        self.sender
+            .send(Message::SetDsp(settings))
+            .context("Failed to send message")
+    }
+
+    fn stop(&self) -> Result<()> {
+        self.sender
+            .send(Message::Stop)
+            .context("Failed to send message")?;
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle
+                .join()
+                .map_err(|_| anyhow::anyhow!("Audio thread panicked"))?;
+        }
+        Ok(())
+    }
+
+    fn events(&self) -> kanal::Receiver<Event> {
+        self.events.clone()
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use rubato::{
+    FastFixedIn, FftFixedIn, PolynomialDegree, SincFixedIn, SincInterpolationParameters,
+    SincInterpolationType, VecResampler, WindowFunction,
+};
+
+use crate::config::{AudioConfig, ResamplerKind};
+
+/// Number of frames handed to the resampler at a time.
+const CHUNK_SIZE: usize = 1024;
+
+/// Converts interleaved samples from one sample rate to another.
+pub struct Resampler {
+    config: AudioConfig,
+    from: u32,
+    to: u32,
+    resampler: Box<dyn VecResampler<f32>>,
+    /// Input frames waiting for a full chunk, one plane per channel.
+    input: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+}
+
+impl Resampler {
+    pub fn new(config: &AudioConfig, from: u32, to: u32, channels: usize) -> Result<Self> {
+        let ratio = to as f64 / from as f64;
+        let resampler: Box<dyn VecResampler<f32>> = match config.resampler {
+            ResamplerKind::Linear => Box::new(FastFixedIn::<f32>::new(
+                ratio,
+                1.0,
+                PolynomialDegree::Linear,
+                CHUNK_SIZE,
+                channels,
+            )?),
+            ResamplerKind::Fast => Box::new(FastFixedIn::<f32>::new(
+                ratio,
+                1.0,
+                PolynomialDegree::Cubic,
+                CHUNK_SIZE,
+                channels,
+            )?),
+            ResamplerKind::Sinc => Box::new(SincFixedIn::<f32>::new(
+                ratio,
+                1.0,
+                SincInterpolationParameters {
+                    sinc_len: config.sinc_len,
+                    f_cutoff: 0.95,
+                    oversampling_factor: 128,
+                    interpolation: SincInterpolationType::Linear,
+                    window: WindowFunction::BlackmanHarris2,
+                },
+                CHUNK_SIZE,
+                channels,
+            )?),
+            ResamplerKind::Fft => Box::new(FftFixedIn::<f32>::new(
+                from as usize,
+                to as usize,
+                CHUNK_SIZE,
+                2,
+                channels,
+            )?),
+        };
+
+        let output = resampler.output_buffer_allocate(true);
+        Ok(Self {
+            config: config.clone(),
+            from,
+            to,
+            resampler,
+            input: vec![Vec::with_capacity(CHUNK_SIZE * 2); channels],
+            output,
+        })
+    }
+
+    /// Resamples interleaved `src` into `dst`, replacing its contents. Frames that do not fill a
+    /// whole chunk are kept until the next call.
+    pub fn process(&mut self, src: &[f32], dst: &mut Vec<f32>) -> Result<()> {
+        dst.clear();
+
+        let channels = self.input.len();
+        for frame in src.chunks_exact(channels) {
+            for (plane, &sample) in self.input.iter_mut().zip(frame) {
+                plane.push(sample);
+            }
+        }
+
+        while self.input[0].len() >= self.resampler.input_frames_next() {
+            let (read, written) =
+                self.resampler
+                    .process_into_buffer(&self.input, &mut self.output, None)?;
+            for plane in self.input.iter_mut() {
+                plane.drain(..read);
+            }
+            self.interleave(written, dst);
+        }
+        Ok(())
+    }
+
+    /// Resamples the frames left over at the end of a track into `dst`, padding them with
+    /// silence.
+    pub fn flush(&mut self, dst: &mut Vec<f32>) -> Result<()> {
+        dst.clear();
+        if self.input[0].is_empty() {
+            return Ok(());
+        }
+
+        let (_, written) = self.resampler.process_partial_into_buffer(
+            Some(&self.input[..]),
+            &mut self.output,
+            None,
+        )?;
+        for plane in self.input.iter_mut() {
+            plane.clear();
+        }
+        self.interleave(written, dst);
+        Ok(())
+    }
+
+    /// Drops the buffered frames and filter state, e.g. after a seek.
+    pub fn reset(&mut self) -> Result<()> {
+        *self = Self::new(&self.config, self.from, self.to, self.input.len())?;
+        Ok(())
+    }
+
+    fn interleave(&self, frames: usize, dst: &mut Vec<f32>) {
+        for i in 0..frames {
+            dst.extend(self.output.iter().map(|plane| plane[i]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use test::Bencher;
+
+    use super::Resampler;
+    use crate::config::{AudioConfig, ResamplerKind};
+
+    fn resampler(kind: ResamplerKind) -> Resampler {
+        let config = AudioConfig {
+            resampler: kind,
+            ..Default::default()
+        };
+        Resampler::new(&config, 48000, 44100, 2).unwrap()
+    }
+
+    #[test]
+    fn test_resample() {
+        for kind in [
+            ResamplerKind::Linear,
+            ResamplerKind::Fast,
+            ResamplerKind::Sinc,
+            ResamplerKind::Fft,
+        ] {
+            let mut resampler = resampler(kind);
+            let (mut dst, mut total) = (vec![], 0);
+            for _ in 0..48 {
+                resampler.process(&[0.0; 2000], &mut dst).unwrap();
+                total += dst.len();
+            }
+            resampler.flush(&mut dst).unwrap();
+            total += dst.len();
+
+            // One second in, one second (give or take a chunk) out.
+            assert!((total as i64 / 2 - 44100).abs() < 2048, "{kind:?}: {total}");
+        }
+    }
+
+    fn bench(b: &mut Bencher, kind: ResamplerKind) {
+        let mut resampler = resampler(kind);
+        let src = (0..8192)
+            .map(|i| (i as f32 * 0.01).sin())
+            .collect::<Vec<_>>();
+        let mut dst = vec![];
+        b.iter(|| resampler.process(&src, &mut dst).unwrap());
+    }
+
+    #[bench]
+    fn bench_linear(b: &mut Bencher) {
+        bench(b, ResamplerKind::Linear);
+    }
+
+    #[bench]
+    fn bench_fast(b: &mut Bencher) {
+        bench(b, ResamplerKind::Fast);
+    }
+
+    #[bench]
+    fn bench_sinc(b: &mut Bencher) {
+        bench(b, ResamplerKind::Sinc);
+    }
+
+    #[bench]
+    fn bench_fft(b: &mut Bencher) {
+        bench(b, ResamplerKind::Fft);
+    }
+}
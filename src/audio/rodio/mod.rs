@@ -1,51 +1,257 @@
-use anyhow::Result;
-use log::warn;
-use rodio::{Decoder, OutputStream};
-use std::fs::File;
-use std::io::BufReader;
+use anyhow::{anyhow, Result};
+use log::{error, warn};
+use rodio::source::SeekError;
+use rodio::{OutputStream, Source};
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
-use crate::audio::Audio;
+use crate::audio::dsp::DspSettings;
+use crate::audio::output::{Output, CHANNELS, SAMPLE_RATE};
+use crate::audio::stream::Stream;
+use crate::audio::{Audio, Event};
+use crate::config::AudioConfig;
+
+/// Silence played while paused, in frames.
+const SILENCE_FRAMES: usize = 512;
 
 pub struct Rodio {
     sink: rodio::Sink,
+    sender: kanal::Sender<Event>,
+    events: kanal::Receiver<Event>,
+    /// DSP settings, picked up by the playing source when they change.
+    dsp: Arc<Mutex<Arc<DspSettings>>>,
+    /// Pauses and seeks, carried out by the playing source once it has faded out.
+    controls: Arc<Mutex<Controls>>,
+    config: AudioConfig,
+}
+
+/// What the playing source should be doing. The sink itself keeps playing, so that the source
+/// can fade out before it goes quiet.
+#[derive(Debug, Default)]
+struct Controls {
+    paused: bool,
+    seek: Option<Duration>,
 }
 
 impl Rodio {
-    pub fn new() -> Result<Self> {
+    pub fn new(config: &AudioConfig) -> Result<Self> {
         let (stream, handle) = OutputStream::try_default()?;
         Box::leak(Box::new(stream));
         let sink = rodio::Sink::try_new(&handle)?;
+        let (sender, events) = kanal::unbounded();
 
-        Ok(Self { sink })
+        Ok(Self {
+            sink,
+            sender,
+            events,
+            dsp: Arc::default(),
+            controls: Arc::default(),
+            config: config.clone(),
+        })
     }
 }
 
 impl Audio for Rodio {
     fn load(&self, path: &Path) -> Result<()> {
-        let file = File::open(path)?;
-        let file = BufReader::new(file);
-        let decoder = Decoder::new(file)?;
-        self.sink.append(decoder);
+        let settings = self.dsp.lock().unwrap().clone();
+        // Loaded while paused, the song fades in once played.
+        let paused = self.controls.lock().unwrap().paused;
+        let mut format = Format {
+            sample_rate: SAMPLE_RATE,
+        };
+        let stream = match Stream::open(path, &mut format, &self.config, &settings, !paused) {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Failed to load song: {e:#}");
+                let _ = self.sender.send(Event::Failed(format!("{e:#}")));
+                return Ok(());
+            }
+        };
+        // Replace the current song rather than queueing after it. Clearing pauses the sink.
+        self.controls.lock().unwrap().seek = None;
+        self.sink.clear();
+        self.sink.append(TrackSource {
+            stream,
+            sample_rate: format.sample_rate,
+            samples: vec![],
+            position: 0,
+            events: self.sender.clone(),
+            shared_settings: self.dsp.clone(),
+            settings,
+            controls: self.controls.clone(),
+        });
+        self.sink.play();
         Ok(())
     }
 
     fn play(&self) -> Result<()> {
-        self.sink.play();
+        self.controls.lock().unwrap().paused = false;
         Ok(())
     }
 
     fn pause(&self) -> Result<()> {
-        self.sink.pause();
+        self.controls.lock().unwrap().paused = true;
+        Ok(())
+    }
+
+    fn seek(&self, position: Duration) -> Result<()> {
+        self.controls.lock().unwrap().seek = Some(position);
+        Ok(())
+    }
+
+    fn set_dsp(&self, settings: DspSettings) -> Result<()> {
+        *self.dsp.lock().unwrap() = Arc::new(settings);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<()> {
+        // Give the source time to fade out before it is cut off. It only looks at the controls
+        // between packets, which are well under 100 ms long.
+        self.controls.lock().unwrap().paused = true;
+        thread::sleep(Duration::from_millis(self.config.fade_ms as u64 + 100));
+        self.sink.stop();
+        Ok(())
+    }
+
+    fn events(&self) -> kanal::Receiver<Event> {
+        self.events.clone()
+    }
+}
+
+/// The format a [`Stream`] is converted to for rodio: stereo, at the usual sample rate or the
+/// song's own with passthrough. Nothing is written to it, as rodio pulls the samples instead.
+struct Format {
+    sample_rate: u32,
+}
+
+impl Output for Format {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        CHANNELS
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        self.sample_rate = sample_rate;
         Ok(())
     }
 
-    fn seek(&self, timestamp: i32) -> Result<()> {
-        self.sink
-            .try_seek(Duration::from_secs(timestamp as u64))
-            .map_err(|e| warn!("Failed to seek: {}", e))
-            .ok();
+    fn write(&mut self, _samples: &[f32]) -> Result<()> {
+        Err(anyhow!("Rodio pulls samples from the stream"))
+    }
+}
+
+/// Feeds a [`Stream`] to rodio, so the simulator decodes and processes exactly like the device.
+/// Reports the end of the song, or why it stopped early, to `events`.
+struct TrackSource {
+    stream: Stream,
+    sample_rate: u32,
+    samples: Vec<f32>,
+    position: usize,
+    events: kanal::Sender<Event>,
+    shared_settings: Arc<Mutex<Arc<DspSettings>>>,
+    settings: Arc<DspSettings>,
+    controls: Arc<Mutex<Controls>>,
+}
+
+impl TrackSource {
+    /// Follows the controls, fading out before pausing or seeking and back in after. Returns
+    /// `false` while paused.
+    fn follow_controls(&mut self) -> bool {
+        let shared = self.controls.clone();
+        let mut controls = shared.lock().unwrap();
+        if !self.stream.is_faded_out() {
+            if controls.paused || controls.seek.is_some() {
+                self.stream.fade_out();
+            } else {
+                self.stream.fade_in();
+            }
+            return true;
+        }
+
+        if let Some(position) = controls.seek.take() {
+            if let Err(e) = self.try_seek(position) {
+                warn!("Failed to seek: {}", e);
+            }
+        }
+        if controls.paused {
+            return false;
+        }
+        self.stream.fade_in();
+        true
+    }
+}
+
+impl Iterator for TrackSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        while self.position >= self.samples.len() {
+            self.position = 0;
+            if !self.follow_controls() {
+                self.samples.clear();
+                self.samples.resize(SILENCE_FRAMES * CHANNELS, 0.0);
+                break;
+            }
+
+            let settings = self.shared_settings.lock().unwrap().clone();
+            if !Arc::ptr_eq(&settings, &self.settings) {
+                self.stream.set_dsp(&settings);
+                self.settings = settings;
+            }
+
+            match self.stream.next() {
+                Ok(Some(samples)) => {
+                    self.samples.clear();
+                    self.samples.extend_from_slice(samples);
+                }
+                Ok(None) => {
+                    let _ = self.events.send(Event::Finished);
+                    return None;
+                }
+                Err(e) => {
+                    error!("Failed to decode song: {e:#}");
+                    let _ = self.events.send(Event::Failed(format!("{e:#}")));
+                    return None;
+                }
+            }
+        }
+
+        let sample = self.samples[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl Source for TrackSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CHANNELS as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let position = self.stream.seek(pos).map_err(|e| {
+            let e: Box<dyn std::error::Error + Send + Sync> = e.into();
+            SeekError::Other(e)
+        })?;
+        self.samples.clear();
+        self.position = 0;
+        let _ = self.events.send(Event::Seeked(position));
         Ok(())
     }
 }
@@ -0,0 +1,129 @@
+use std::f32::consts::PI;
+
+use crate::audio::dsp::Dsp;
+
+/// Bauer stereophonic-to-binaural crossfeed, after bs2b: each ear also hears the other channel,
+/// low-passed and delayed as it would be from a speaker, which takes the edge off hard-panned
+/// recordings on headphones. Only stereo is processed.
+pub struct Crossfeed {
+    a0_lo: f32,
+    b1_lo: f32,
+    a0_hi: f32,
+    a1_hi: f32,
+    b1_hi: f32,
+    gain: f32,
+    /// Last input, low-passed and high-boosted output of each channel.
+    input: [f32; 2],
+    lo: [f32; 2],
+    hi: [f32; 2],
+}
+
+impl Crossfeed {
+    /// Crossfeeds below `cutoff` Hz at `level` dB below the direct signal.
+    pub fn new(cutoff: f32, level: f32, sample_rate: u32) -> Self {
+        let gain_lo = level * -5.0 / 6.0 - 3.0;
+        let gain_hi = level / 6.0 - 3.0;
+
+        let g_lo = 10f32.powf(gain_lo / 20.0);
+        let g_hi = 1.0 - 10f32.powf(gain_hi / 20.0);
+        let cutoff_hi = cutoff * 2f32.powf((gain_lo - 20.0 * g_hi.log10()) / 12.0);
+
+        let x = (-2.0 * PI * cutoff / sample_rate as f32).exp();
+        let (a0_lo, b1_lo) = (g_lo * (1.0 - x), x);
+
+        let x = (-2.0 * PI * cutoff_hi / sample_rate as f32).exp();
+        let (a0_hi, a1_hi, b1_hi) = (1.0 - g_hi * (1.0 - x), -x, x);
+
+        Self {
+            a0_lo,
+            b1_lo,
+            a0_hi,
+            a1_hi,
+            b1_hi,
+            gain: 1.0 / (1.0 - g_hi + g_lo),
+            input: [0.0; 2],
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+        }
+    }
+}
+
+impl Dsp for Crossfeed {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            for c in 0..2 {
+                self.lo[c] = self.a0_lo * frame[c] + self.b1_lo * self.lo[c];
+                self.hi[c] =
+                    self.a0_hi * frame[c] + self.a1_hi * self.input[c] + self.b1_hi * self.hi[c];
+                self.input[c] = frame[c];
+            }
+            frame[0] = (self.hi[0] + self.lo[1]) * self.gain;
+            frame[1] = (self.hi[1] + self.lo[0]) * self.gain;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input = [0.0; 2];
+        self.lo = [0.0; 2];
+        self.hi = [0.0; 2];
+    }
+}
+
+/// Stereo width and left/right balance. Only stereo is processed.
+pub struct Stereo {
+    /// Gain of the side signal: 0 is mono, 1 unchanged, above 1 wider.
+    width: f32,
+    /// Gain of the left and right channels.
+    balance: [f32; 2],
+}
+
+impl Stereo {
+    /// `balance` goes from -1, left only, to 1, right only.
+    pub fn new(width: f32, balance: f32) -> Self {
+        let balance = balance.clamp(-1.0, 1.0);
+        Self {
+            width: width.max(0.0),
+            balance: [(1.0 - balance).min(1.0), (1.0 + balance).min(1.0)],
+        }
+    }
+}
+
+impl Dsp for Stereo {
+    fn process(&mut self, samples: &mut [f32]) {
+        // Widening can push the side signal past full scale, so scale both down to keep peaks.
+        let scale = 1.0 / self.width.max(1.0);
+        for frame in samples.chunks_exact_mut(2) {
+            let mid = (frame[0] + frame[1]) * 0.5;
+            let side = (frame[0] - frame[1]) * 0.5 * self.width;
+            frame[0] = (mid + side) * scale * self.balance[0];
+            frame[1] = (mid - side) * scale * self.balance[1];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Crossfeed, Stereo};
+    use crate::audio::dsp::Dsp;
+
+    #[test]
+    fn test_stereo() {
+        let mut samples = [1.0, 0.0, 0.5, 0.5];
+        Stereo::new(0.0, 0.0).process(&mut samples);
+        assert_eq!(samples, [0.5, 0.5, 0.5, 0.5]);
+
+        let mut samples = [1.0, 1.0];
+        Stereo::new(1.0, 0.5).process(&mut samples);
+        assert_eq!(samples, [0.5, 1.0]);
+
+        // A hard-panned tone leaks into the other ear, a centered one is left alone.
+        let mut crossfeed = Crossfeed::new(700.0, 4.5, 44100);
+        let mut samples = [1.0, 0.0].repeat(4410);
+        crossfeed.process(&mut samples);
+        assert!(samples[samples.len() - 1] > 0.1);
+        let mut crossfeed = Crossfeed::new(700.0, 4.5, 44100);
+        let mut samples = [0.5; 8820];
+        crossfeed.process(&mut samples);
+        assert!((samples[samples.len() - 1] - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use crate::audio::dsp::{Chain, DspSettings};
+use crate::audio::fade::Ramp;
+use crate::audio::mixer::Mixer;
+use crate::audio::output::Output;
+use crate::audio::resampler::Resampler;
+use crate::audio::stretch::Stretcher;
+use crate::audio::track::Track;
+use crate::config::AudioConfig;
+
+/// A track converted to the sample rate and channels of an output.
+pub struct Stream {
+    track: Track,
+    resampler: Option<Resampler>,
+    mixer: Mixer,
+    stretcher: Stretcher,
+    dsp: Chain,
+    fade: Ramp,
+    resampled: Vec<f32>,
+    mixed: Vec<f32>,
+    stretched: Vec<f32>,
+    flushed: bool,
+}
+
+impl Stream {
+    /// Opens the song at `path` for playing on `output`. With passthrough enabled, the output is
+    /// first asked to switch to the song's sample rate. Unless `is_playing`, the stream starts
+    /// silent and fades in once played.
+    pub fn open(
+        path: &Path,
+        output: &mut dyn Output,
+        config: &AudioConfig,
+        dsp: &DspSettings,
+        is_playing: bool,
+    ) -> Result<Self> {
+        let track = Track::open(path)?;
+
+        if config.passthrough && output.sample_rate() != track.sample_rate() {
+            if let Err(e) = output.set_sample_rate(track.sample_rate()) {
+                warn!("Failed to set output sample rate: {e:#}");
+            }
+        }
+
+        let resampler = if track.sample_rate() != output.sample_rate() {
+            debug!(
+                "resampling {} Hz to {} Hz with {:?}",
+                track.sample_rate(),
+                output.sample_rate(),
+                config.resampler
+            );
+            Some(Resampler::new(
+                config,
+                track.sample_rate(),
+                output.sample_rate(),
+                track.channels(),
+            )?)
+        } else {
+            None
+        };
+        let mixer = Mixer::new(track.channels(), track.layout(), output.channels());
+        let mut stretcher = Stretcher::new(output.sample_rate(), output.channels());
+        stretcher.set_speed(dsp.speed);
+        let dsp = Chain::new(dsp, output.sample_rate(), output.channels());
+        let fade = Ramp::new(
+            output.sample_rate(),
+            output.channels(),
+            config.fade_ms,
+            if is_playing { 1.0 } else { 0.0 },
+        );
+
+        Ok(Self {
+            track,
+            resampler,
+            mixer,
+            stretcher,
+            dsp,
+            fade,
+            resampled: vec![],
+            mixed: vec![],
+            stretched: vec![],
+            flushed: false,
+        })
+    }
+
+    /// Returns the next interleaved samples for the output, or `None` at the end of the track.
+    pub fn next(&mut self) -> Result<Option<&[f32]>> {
+        let samples = match self.track.next()? {
+            Some(samples) => match self.resampler {
+                Some(ref mut resampler) => {
+                    resampler.process(samples, &mut self.resampled)?;
+                    &self.resampled[..]
+                }
+                None => samples,
+            },
+            None => match self.resampler {
+                Some(ref mut resampler) if !self.flushed => {
+                    self.flushed = true;
+                    resampler.flush(&mut self.resampled)?;
+                    &self.resampled[..]
+                }
+                _ if !self.stretcher.is_empty() => {
+                    self.stretcher.flush(&mut self.stretched);
+                    self.dsp.process(&mut self.stretched);
+                    self.fade.process(&mut self.stretched);
+                    return Ok(Some(&self.stretched));
+                }
+                _ => return Ok(None),
+            },
+        };
+
+        self.mixer.mix(samples, &mut self.mixed);
+        let samples = if self.stretcher.is_active() {
+            self.stretcher.process(&self.mixed, &mut self.stretched);
+            &mut self.stretched
+        } else {
+            &mut self.mixed
+        };
+        self.dsp.process(samples);
+        self.fade.process(samples);
+        Ok(Some(samples))
+    }
+
+    /// Starts fading in from where the last fade left off.
+    pub fn fade_in(&mut self) {
+        self.fade.set(1.0);
+    }
+
+    /// Starts fading out, see [`Stream::is_faded_out`].
+    pub fn fade_out(&mut self) {
+        self.fade.set(0.0);
+    }
+
+    /// Whether the stream has faded out, so that it can be stopped without a click.
+    pub fn is_faded_out(&self) -> bool {
+        self.fade.is_silent()
+    }
+
+    pub fn set_dsp(&mut self, settings: &DspSettings) {
+        self.stretcher.set_speed(settings.speed);
+        self.dsp.configure(settings);
+    }
+
+    /// Seeks to `position` and returns where playback resumes.
+    pub fn seek(&mut self, position: Duration) -> Result<Duration> {
+        let position = self.track.seek(position)?;
+        if let Some(ref mut resampler) = self.resampler {
+            resampler.reset()?;
+        }
+        self.stretcher.reset();
+        self.dsp.reset();
+        self.flushed = false;
+        Ok(position)
+    }
+}
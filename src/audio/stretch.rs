@@ -0,0 +1,185 @@
+/// Slowest playback speed.
+pub const MIN_SPEED: f32 = 0.5;
+/// Fastest playback speed.
+pub const MAX_SPEED: f32 = 3.0;
+
+/// Length of the segments that are cut from the input and spliced together, in ms.
+const SEGMENT_MS: usize = 40;
+/// Length of the crossfade between segments, in ms.
+const OVERLAP_MS: usize = 10;
+/// How far a segment may be moved to line up with the previous one, in ms.
+const SEARCH_MS: usize = 15;
+
+/// Changes the speed of interleaved audio without changing its pitch, with WSOLA: segments are
+/// taken from the input at the speed's pace, each nudged to where it best matches the end of the
+/// previous one, and crossfaded together.
+pub struct Stretcher {
+    channels: usize,
+    speed: f32,
+    segment: usize,
+    overlap: usize,
+    search: usize,
+    /// Input not yet consumed.
+    input: Vec<f32>,
+    /// Where the next segment would start without nudging, in frames into `input`.
+    position: f64,
+    /// End of the last segment, to crossfade into the next one.
+    tail: Vec<f32>,
+}
+
+impl Stretcher {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let frames = |ms: usize| sample_rate as usize * ms / 1000;
+        Self {
+            channels,
+            speed: 1.0,
+            segment: frames(SEGMENT_MS),
+            overlap: frames(OVERLAP_MS),
+            search: frames(SEARCH_MS),
+            input: vec![],
+            position: 0.0,
+            tail: vec![],
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// Whether the stretcher does anything, or samples can skip it.
+    pub fn is_active(&self) -> bool {
+        self.speed != 1.0 || !self.is_empty()
+    }
+
+    /// Whether there is no input left over from earlier calls.
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty() && self.tail.is_empty()
+    }
+
+    /// Forgets the buffered input, e.g. after a seek.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.tail.clear();
+    }
+
+    /// Stretches interleaved `src` into `dst`, replacing its contents. Input that does not make a
+    /// whole segment is kept until the next call.
+    pub fn process(&mut self, src: &[f32], dst: &mut Vec<f32>) {
+        dst.clear();
+        if self.speed == 1.0 {
+            // Back to normal speed, play out what is left and pass the rest through.
+            self.drain(dst);
+            dst.extend_from_slice(src);
+            return;
+        }
+
+        let c = self.channels;
+        self.input.extend_from_slice(src);
+        loop {
+            let start = (self.position as usize).saturating_sub(self.search);
+            if (start + 2 * self.search + self.segment) * c > self.input.len() {
+                break;
+            }
+
+            let begin = if self.tail.is_empty() {
+                self.position as usize
+            } else {
+                start + self.best_offset(start)
+            };
+
+            let segment = &self.input[begin * c..(begin + self.segment) * c];
+            let (head, rest) = segment.split_at(self.overlap * c);
+            if self.tail.is_empty() {
+                dst.extend_from_slice(head);
+            } else {
+                for (i, (a, b)) in self.tail.iter().zip(head).enumerate() {
+                    let t = (i / c) as f32 / self.overlap as f32;
+                    dst.push(a * (1.0 - t) + b * t);
+                }
+            }
+            let (body, tail) = rest.split_at(rest.len() - self.overlap * c);
+            dst.extend_from_slice(body);
+            self.tail.clear();
+            self.tail.extend_from_slice(tail);
+
+            self.position += (self.segment - self.overlap) as f64 * self.speed as f64;
+            let consumed = (self.position as usize)
+                .saturating_sub(self.search)
+                .min(self.input.len() / c);
+            self.input.drain(..consumed * c);
+            self.position -= consumed as f64;
+        }
+    }
+
+    /// Plays out the input left over at the end of a track into `dst`.
+    pub fn flush(&mut self, dst: &mut Vec<f32>) {
+        dst.clear();
+        self.drain(dst);
+    }
+
+    fn drain(&mut self, dst: &mut Vec<f32>) {
+        dst.extend_from_slice(&self.tail);
+        let start = (self.position as usize * self.channels).min(self.input.len());
+        dst.extend_from_slice(&self.input[start..]);
+        self.reset();
+    }
+
+    /// Finds where in the search window from `start` the input best continues the tail, by
+    /// normalized cross-correlation. Only every other offset and frame is tried, to keep it cheap.
+    fn best_offset(&self, start: usize) -> usize {
+        let c = self.channels;
+        let mono = |samples: &[f32], frame: usize| -> f32 {
+            samples[frame * c..(frame + 1) * c].iter().sum()
+        };
+
+        let mut best = (f32::MIN, self.search);
+        for offset in (0..=2 * self.search).step_by(2) {
+            let (mut correlation, mut energy) = (0.0, 0.0);
+            for frame in (0..self.overlap).step_by(2) {
+                let x = mono(&self.input, start + offset + frame);
+                correlation += x * mono(&self.tail, frame);
+                energy += x * x;
+            }
+            let score = correlation / (energy + 1e-9).sqrt();
+            if score > best.0 {
+                best = (score, offset);
+            }
+        }
+        best.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stretcher;
+
+    #[test]
+    fn test_stretch() {
+        // At normal speed samples pass through.
+        let mut stretcher = Stretcher::new(44100, 2);
+        let mut dst = vec![];
+        stretcher.process(&[0.1, 0.2], &mut dst);
+        assert_eq!(dst, [0.1, 0.2]);
+
+        // Otherwise the length scales with the speed.
+        for speed in [0.5, 1.5, 3.0] {
+            let mut stretcher = Stretcher::new(44100, 2);
+            stretcher.set_speed(speed);
+            let src: Vec<f32> = (0..44100 * 2).map(|i| (i as f32 * 0.01).sin()).collect();
+            let mut frames = 0;
+            for chunk in src.chunks(2048) {
+                stretcher.process(chunk, &mut dst);
+                frames += dst.len() / 2;
+            }
+            stretcher.flush(&mut dst);
+            frames += dst.len() / 2;
+
+            let expected = 44100.0 / speed;
+            assert!(
+                (frames as f32 - expected).abs() < expected * 0.05,
+                "{speed}: {frames}"
+            );
+        }
+    }
+}
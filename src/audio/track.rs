@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use symphonia::core::audio::Channels;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+/// Consecutive packets that may fail to decode before the file is considered corrupt.
+const MAX_DECODE_ERRORS: usize = 16;
+
+/// A song being decoded into interleaved samples, shared by every backend so they all decode
+/// alike.
+pub struct Track {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    sample_rate: u32,
+    channels: usize,
+    /// Where the channels go, as the decoder reported them.
+    layout: Option<Channels>,
+    samples: Vec<f32>,
+    /// Whether `samples` holds the packet decoded by `open` that has not been returned yet.
+    pending: bool,
+    finished: bool,
+    errors: usize,
+    /// Samples still to be discarded to land exactly on the last seek position.
+    skip: usize,
+}
+
+impl Track {
+    /// Opens the default track of the file at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+
+        let mss_opts = MediaSourceStreamOptions::default();
+        let mss = MediaSourceStream::new(Box::new(file), mss_opts);
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension() {
+            hint.with_extension(&ext.to_string_lossy());
+        };
+
+        let meta_opts = MetadataOptions::default();
+        let fmt_opts = FormatOptions::default();
+
+        let format = symphonia::default::get_probe().format(&hint, mss, fmt_opts, meta_opts)?;
+
+        let Some(track) = format.default_track() else {
+            return Err(anyhow!("No audio track in {}", path.display()));
+        };
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+
+        let dec_opts = DecoderOptions::default();
+        let decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
+
+        let mut track = Self {
+            format,
+            decoder,
+            track_id,
+            time_base,
+            sample_rate: 0,
+            channels: 0,
+            layout: None,
+            samples: vec![],
+            pending: false,
+            finished: false,
+            errors: 0,
+            skip: 0,
+        };
+
+        // Decode the first packet to find out the sample rate and channel layout.
+        track.pending = track.decode()?;
+        if track.channels == 0 {
+            return Err(anyhow!("No audio in {}", path.display()));
+        }
+        Ok(track)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn layout(&self) -> Option<&Channels> {
+        self.layout.as_ref()
+    }
+
+    /// Returns the next interleaved samples, or `None` at the end of the track.
+    pub fn next(&mut self) -> Result<Option<&[f32]>> {
+        if std::mem::take(&mut self.pending) || self.decode()? {
+            Ok(Some(&self.samples))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Seeks to `position` and returns where playback resumes. The samples between the packet
+    /// the format lands on and `position` are discarded, so the position is exact whenever the
+    /// track has a time base.
+    pub fn seek(&mut self, position: Duration) -> Result<Duration> {
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::new(position.as_secs(), position.subsec_nanos() as f64 / 1e9),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.samples.clear();
+        self.pending = false;
+        self.finished = false;
+        self.skip = 0;
+
+        let Some(time_base) = self.time_base else {
+            return Ok(position);
+        };
+        let required = to_duration(time_base.calc_time(seeked.required_ts));
+        let actual = to_duration(time_base.calc_time(seeked.actual_ts));
+        let frames =
+            (required.saturating_sub(actual).as_secs_f64() * self.sample_rate as f64).round();
+        self.skip = frames as usize * self.channels;
+        debug!("seeked to {actual:?}, skipping {frames} frames to {required:?}");
+        Ok(required)
+    }
+
+    /// Decodes packets into `samples` until there are some. Returns `false` at the end of the
+    /// track.
+    fn decode(&mut self) -> Result<bool> {
+        while !self.finished {
+            let packet = match self.format.next_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    self.finished = true;
+                    return Ok(false);
+                }
+                // Some formats report the end of the stream as an unexpected EOF.
+                Err(Error::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    self.finished = true;
+                    return Ok(false);
+                }
+                Err(Error::ResetRequired) => {
+                    // The tracks changed, e.g. in a chained Ogg stream. Start over with the new
+                    // default track.
+                    self.reset()?;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // If the packet does not belong to the selected track, skip over it.
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            // Decode the packet into audio samples.
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    if self.channels == 0 {
+                        let spec = decoded.spec();
+                        self.sample_rate = spec.rate();
+                        self.channels = spec.channels().count();
+                        self.layout = Some(spec.channels().clone());
+                        debug!(
+                            "{} channels ({:?}) at {} Hz",
+                            self.channels,
+                            spec.channels(),
+                            self.sample_rate
+                        );
+                    }
+                    decoded.copy_to_vec_interleaved(&mut self.samples);
+                    self.errors = 0;
+                    if self.skip > 0 {
+                        let skipped = self.skip.min(self.samples.len());
+                        self.samples.drain(..skipped);
+                        self.skip -= skipped;
+                    }
+                    if !self.samples.is_empty() {
+                        return Ok(true);
+                    }
+                }
+                Err(Error::IoError(e)) => {
+                    // The packet failed to decode due to an IO error, skip the packet.
+                    warn!("{e:?}");
+                    self.skip_packet()?;
+                }
+                Err(Error::DecodeError(e)) => {
+                    // The packet failed to decode due to invalid data, skip the packet.
+                    warn!("{e:?}");
+                    self.skip_packet()?;
+                }
+                Err(Error::ResetRequired) => self.decoder.reset(),
+                Err(err) => {
+                    // An unrecoverable error occurred, halt decoding.
+                    return Err(err.into());
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Counts a packet that failed to decode, giving up once too many fail in a row.
+    fn skip_packet(&mut self) -> Result<()> {
+        self.errors += 1;
+        if self.errors >= MAX_DECODE_ERRORS {
+            return Err(anyhow!("{} packets in a row failed to decode", self.errors));
+        }
+        Ok(())
+    }
+
+    /// Recreates the decoder for the current default track.
+    fn reset(&mut self) -> Result<()> {
+        let Some(track) = self.format.default_track() else {
+            return Err(anyhow!("No audio track left"));
+        };
+        self.track_id = track.id;
+        self.time_base = track.codec_params.time_base;
+        self.decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+        Ok(())
+    }
+}
+
+fn to_duration(time: Time) -> Duration {
+    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+}
@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use anyhow::Result;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use symphonia::core::conv::IntoSample;
+
+use crate::audio::output::{Output, CHANNELS, SAMPLE_RATE};
+
+/// Records the samples that would have been sent to the device into a 16-bit WAV file. The file
+/// is finalized when the output is dropped.
+pub struct Wav {
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl Wav {
+    pub fn create(path: &Path) -> Result<Self> {
+        let spec = WavSpec {
+            channels: CHANNELS as u16,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        Ok(Self {
+            writer: WavWriter::create(path, spec)?,
+        })
+    }
+}
+
+impl Output for Wav {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn channels(&self) -> usize {
+        CHANNELS
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let mut writer = self.writer.get_i16_writer(samples.len() as u32);
+        for &sample in samples {
+            writer.write_sample::<i16>(sample.into_sample());
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hound::WavReader;
+    use symphonia::core::conv::IntoSample;
+
+    use super::Wav;
+    use crate::audio::output::{Output, SAMPLE_RATE};
+
+    #[test]
+    fn test_write() {
+        let path = std::env::temp_dir().join("vinyl-test-write.wav");
+        let samples = [0.0, 0.5, -0.5, 1.0, -1.0, 0.25];
+
+        let mut wav = Wav::create(&path).unwrap();
+        wav.write(&samples).unwrap();
+        wav.write(&samples).unwrap();
+        drop(wav);
+
+        let mut reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE);
+        let written = reader
+            .samples::<i16>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = samples.map(|sample| sample.into_sample::<i16>());
+        assert_eq!(written, [expected, expected].concat());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
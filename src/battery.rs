@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Approximate discharge curve of a single Li-ion cell, as (millivolts, percent) pairs. Used
+/// when the driver only reports a voltage.
+const DISCHARGE_CURVE: [(u32, u8); 9] = [
+    (3300, 0),
+    (3500, 5),
+    (3600, 10),
+    (3700, 25),
+    (3750, 40),
+    (3800, 55),
+    (3900, 70),
+    (4000, 85),
+    (4150, 100),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BatteryStatus {
+    /// Remaining charge, in percent.
+    pub level: u8,
+    pub charging: bool,
+}
+
+pub enum Battery {
+    /// A `/sys/class/power_supply` battery device.
+    #[cfg_attr(feature = "simulator", allow(unused))]
+    Sysfs(PathBuf),
+    /// A made-up battery for the simulator, set with `VINYL_FAKE_BATTERY=<level>[,charging]`.
+    #[cfg_attr(not(feature = "simulator"), allow(unused))]
+    Fake,
+}
+
+impl Battery {
+    #[cfg(not(feature = "simulator"))]
+    pub fn open() -> Result<Self> {
+        for entry in fs::read_dir("/sys/class/power_supply")? {
+            let path = entry?.path();
+            let kind = fs::read_to_string(path.join("type")).unwrap_or_default();
+            if kind.trim() == "Battery" {
+                return Ok(Self::Sysfs(path));
+            }
+        }
+        Err(anyhow::anyhow!("No battery found"))
+    }
+
+    #[cfg(feature = "simulator")]
+    pub fn open() -> Result<Self> {
+        Ok(Self::Fake)
+    }
+
+    pub fn status(&self) -> Result<BatteryStatus> {
+        match self {
+            Self::Sysfs(path) => {
+                let level = match read_u32(&path.join("capacity")) {
+                    Ok(capacity) => capacity.min(100) as u8,
+                    Err(_) => {
+                        let microvolts = read_u32(&path.join("voltage_now"))?;
+                        voltage_to_percent(microvolts / 1000)
+                    }
+                };
+                let status = fs::read_to_string(path.join("status")).unwrap_or_default();
+                Ok(BatteryStatus {
+                    level,
+                    charging: matches!(status.trim(), "Charging" | "Full"),
+                })
+            }
+            Self::Fake => {
+                let fake = std::env::var("VINYL_FAKE_BATTERY").unwrap_or_default();
+                let mut parts = fake.split(',');
+                Ok(BatteryStatus {
+                    level: parts
+                        .next()
+                        .and_then(|level| level.trim().parse().ok())
+                        .unwrap_or(100),
+                    charging: parts.next().is_some_and(|s| s.trim() == "charging"),
+                })
+            }
+        }
+    }
+}
+
+/// Estimates the remaining charge from the cell voltage.
+fn voltage_to_percent(millivolts: u32) -> u8 {
+    let (first, last) = (DISCHARGE_CURVE[0], DISCHARGE_CURVE[DISCHARGE_CURVE.len() - 1]);
+    if millivolts <= first.0 {
+        return first.1;
+    }
+    if millivolts >= last.0 {
+        return last.1;
+    }
+
+    DISCHARGE_CURVE
+        .windows(2)
+        .find(|w| millivolts < w[1].0)
+        .map(|w| {
+            let ((v0, p0), (v1, p1)) = (w[0], w[1]);
+            (p0 as u32 + (millivolts - v0) * (p1 - p0) as u32 / (v1 - v0)) as u8
+        })
+        .unwrap_or(last.1)
+}
+
+fn read_u32(path: &Path) -> Result<u32> {
+    fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::voltage_to_percent;
+
+    #[test]
+    fn test_voltage_to_percent() {
+        assert_eq!(voltage_to_percent(3000), 0);
+        assert_eq!(voltage_to_percent(3300), 0);
+        assert_eq!(voltage_to_percent(3550), 7);
+        assert_eq!(voltage_to_percent(3700), 25);
+        assert_eq!(voltage_to_percent(4150), 100);
+        assert_eq!(voltage_to_percent(4300), 100);
+    }
+}
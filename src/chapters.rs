@@ -0,0 +1,464 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use lofty::tag::{ItemKey, Tag};
+use log::debug;
+
+/// A chapter of an audiobook or podcast.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+}
+
+/// Reads the chapters of `file`, from ID3v2 `CHAP` frames, the chapter track or Nero `chpl` atom
+/// of MP4 files, or `CHAPTERxxx` Vorbis comments. Returns them sorted by start time, or none if there are none.
+pub fn read(file: &mut File, tag: Option<&Tag>) -> Vec<Chapter> {
+    let mut chapters = match read_id3(file).and_then(|chapters| match chapters {
+        chapters if !chapters.is_empty() => Ok(chapters),
+        _ => read_mp4(file),
+    }) {
+        Ok(chapters) => chapters,
+        Err(e) => {
+            debug!("no chapters: {e:#}");
+            vec![]
+        }
+    };
+    if chapters.is_empty() {
+        chapters = tag.map(read_vorbis).unwrap_or_default();
+    }
+
+    chapters.sort_by_key(|chapter| chapter.start);
+    let _ = file.seek(SeekFrom::Start(0));
+    chapters
+}
+
+fn read_id3(file: &mut File) -> Result<Vec<Chapter>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+    if &header[..3] != b"ID3" || header[3] < 3 {
+        return Ok(vec![]);
+    }
+    let version = header[3];
+    let size = syncsafe(&header[6..10]) as u64;
+    if size + 10 > file.metadata()?.len() {
+        return Err(anyhow!("ID3 tag of {size} bytes is larger than the file"));
+    }
+    let mut tag = vec![0; size as usize];
+    file.read_exact(&mut tag)?;
+
+    // Skip the extended header.
+    let mut body = &tag[..];
+    if header[5] & 0x40 != 0 && body.len() >= 4 {
+        let size = match version {
+            3 => u32::from_be_bytes(body[..4].try_into()?) as usize + 4,
+            _ => syncsafe(&body[..4]) as usize,
+        };
+        body = body.get(size..).unwrap_or_default();
+    }
+
+    Ok(id3_frames(body, version)
+        .filter(|(id, _)| id == b"CHAP")
+        .filter_map(|(_, frame)| {
+            // The element ID, then the start and end times in ms and byte offsets.
+            let end = frame.iter().position(|&b| b == 0)? + 1;
+            let start = u32::from_be_bytes(frame.get(end..end + 4)?.try_into().ok()?);
+            let title = id3_frames(frame.get(end + 16..)?, version)
+                .find(|(id, _)| id == b"TIT2")
+                .map(|(_, text)| id3_text(text))
+                .unwrap_or_default();
+            Some(Chapter {
+                title,
+                start: Duration::from_millis(start as u64),
+            })
+        })
+        .collect())
+}
+
+/// Iterates over the ID and contents of the ID3v2 frames in `data`.
+fn id3_frames(mut data: &[u8], version: u8) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 10 || data[0] == 0 {
+            return None;
+        }
+        let size = match version {
+            3 => u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            _ => syncsafe(&data[4..8]),
+        } as usize;
+        let frame = (&data[..4], data.get(10..10 + size)?);
+        data = &data[10 + size..];
+        Some(frame)
+    })
+}
+
+/// Decodes the contents of an ID3v2 text frame.
+fn id3_text(data: &[u8]) -> String {
+    let Some((&encoding, text)) = data.split_first() else {
+        return String::new();
+    };
+    let utf16 = |text: &[u8], big_endian: bool| {
+        let units: Vec<u16> = text
+            .chunks_exact(2)
+            .map(|b| match big_endian {
+                true => u16::from_be_bytes([b[0], b[1]]),
+                false => u16::from_le_bytes([b[0], b[1]]),
+            })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    let text = match encoding {
+        0 => text.iter().map(|&b| b as char).collect(),
+        1 if text.starts_with(&[0xff, 0xfe]) => utf16(&text[2..], false),
+        1 if text.starts_with(&[0xfe, 0xff]) => utf16(&text[2..], true),
+        1 | 2 => utf16(text, true),
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, &b| (size << 7) | (b & 0x7f) as u32)
+}
+
+fn read_mp4(file: &mut File) -> Result<Vec<Chapter>> {
+    // Only look for atoms in files that start with an `ftyp` atom, not in any file without ID3
+    // chapters.
+    let mut header = [0; 8];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if &header[4..8] != b"ftyp" {
+        return Ok(vec![]);
+    }
+
+    let len = file.seek(SeekFrom::End(0))?;
+    let Some((start, end)) = find_atom(file, 0, len, &[b"moov"])? else {
+        return Ok(vec![]);
+    };
+    let chapters = read_chapter_track(file, start, end)?;
+    if !chapters.is_empty() {
+        return Ok(chapters);
+    }
+    match find_atom(file, start, end, &[b"udta", b"chpl"])? {
+        Some(chpl) => read_chpl(&read_atom(file, chpl)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Reads the QuickTime chapter track of the `moov` atom between `start` and `end`: a text track
+/// that another track refers to with `tref/chap`, with one sample per chapter. This is where
+/// iTunes and Audible books keep their chapters.
+fn read_chapter_track(file: &mut File, start: u64, end: u64) -> Result<Vec<Chapter>> {
+    let traks: Vec<_> = atoms(file, start, end)?
+        .into_iter()
+        .filter(|(name, ..)| name == b"trak")
+        .collect();
+
+    let mut ids = vec![];
+    for &(_, start, end) in &traks {
+        if let Some(chap) = find_atom(file, start, end, &[b"tref", b"chap"])? {
+            let chap = read_atom(file, chap)?;
+            ids.extend(
+                chap.chunks_exact(4)
+                    .map(|id| u32::from_be_bytes(id.try_into().unwrap())),
+            );
+        }
+    }
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    for &(_, start, end) in &traks {
+        let Some(tkhd) = find_atom(file, start, end, &[b"tkhd"])? else {
+            continue;
+        };
+        // Version and flags, then the creation and modification times, which are 64-bit in
+        // version 1.
+        let tkhd = read_atom(file, tkhd)?;
+        let id = be_u32(&tkhd, if tkhd.first() == Some(&1) { 20 } else { 12 })?;
+        if ids.contains(&id) {
+            return read_text_track(file, start, end);
+        }
+    }
+    Ok(vec![])
+}
+
+/// Reads the samples of the text track in the `trak` atom between `start` and `end` as chapters.
+fn read_text_track(file: &mut File, start: u64, end: u64) -> Result<Vec<Chapter>> {
+    let mut table = |path: &[&[u8; 4]]| -> Result<Option<Vec<u8>>> {
+        match find_atom(file, start, end, path)? {
+            Some(atom) => Ok(Some(read_atom(file, atom)?)),
+            None => Ok(None),
+        }
+    };
+    let mdhd = table(&[b"mdia", b"mdhd"])?.ok_or_else(|| anyhow!("No mdhd atom"))?;
+    let stbl = |name| [b"mdia", b"minf", b"stbl", name];
+    let stts = table(&stbl(b"stts"))?.ok_or_else(|| anyhow!("No stts atom"))?;
+    let stsz = table(&stbl(b"stsz"))?.ok_or_else(|| anyhow!("No stsz atom"))?;
+    let stsc = table(&stbl(b"stsc"))?.ok_or_else(|| anyhow!("No stsc atom"))?;
+    let (chunks, wide) = match table(&stbl(b"stco"))? {
+        Some(stco) => (stco, false),
+        None => (
+            table(&stbl(b"co64"))?.ok_or_else(|| anyhow!("No chunk offsets"))?,
+            true,
+        ),
+    };
+
+    // Like `tkhd`, but for the media, with the time scale after the times.
+    let timescale = be_u32(&mdhd, if mdhd.first() == Some(&1) { 20 } else { 12 })?.max(1);
+    // The tables start with their version and flags, then the number of entries.
+    let starts = (0..be_u32(&stts, 4)? as usize)
+        .map(|i| Ok((be_u32(&stts, 8 + i * 8)?, be_u32(&stts, 12 + i * 8)?)))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flat_map(|(count, delta)| std::iter::repeat(delta as u64).take(count as usize))
+        .scan(0, |time, delta| {
+            let start = *time;
+            *time += delta;
+            Some(start)
+        });
+    let sample_size = be_u32(&stsz, 4)?;
+    let sizes = (0..be_u32(&stsz, 8)? as usize)
+        .map(|i| match sample_size {
+            0 => be_u32(&stsz, 12 + i * 4),
+            size => Ok(size),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let runs = (0..be_u32(&stsc, 4)? as usize)
+        .map(|i| Ok((be_u32(&stsc, 8 + i * 12)?, be_u32(&stsc, 12 + i * 12)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Samples are stored in chunks, with runs of chunks holding the same number of samples.
+    let mut offsets = vec![];
+    for chunk in 0..be_u32(&chunks, 4)? as usize {
+        let mut offset = match wide {
+            true => u64::from_be_bytes(
+                chunks
+                    .get(8 + chunk * 8..16 + chunk * 8)
+                    .ok_or_else(|| anyhow!("Truncated co64 atom"))?
+                    .try_into()?,
+            ),
+            false => be_u32(&chunks, 8 + chunk * 4)? as u64,
+        };
+        let samples = runs
+            .iter()
+            .take_while(|&&(first, _)| first as usize <= chunk + 1)
+            .last()
+            .map_or(0, |&(_, samples)| samples);
+        for _ in 0..samples {
+            let Some(&size) = sizes.get(offsets.len()) else {
+                break;
+            };
+            offsets.push((offset, size));
+            offset = offset.saturating_add(size as u64);
+        }
+    }
+
+    let len = file.metadata()?.len();
+    offsets
+        .into_iter()
+        .zip(starts)
+        .map(|((offset, size), start)| {
+            if offset.saturating_add(size as u64) > len {
+                return Err(anyhow!(
+                    "Chapter sample at {offset} is past the end of the file"
+                ));
+            }
+            let mut sample = vec![0; size as usize];
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut sample)?;
+            Ok(Chapter {
+                title: mp4_text(&sample),
+                start: Duration::from_millis(start * 1000 / timescale as u64),
+            })
+        })
+        .collect()
+}
+
+/// Decodes a text sample: its length, then the text, which is UTF-16 if it starts with a BOM.
+fn mp4_text(sample: &[u8]) -> String {
+    let len = sample
+        .get(..2)
+        .map_or(0, |len| u16::from_be_bytes([len[0], len[1]]) as usize);
+    let text = sample.get(2..2 + len).unwrap_or_default();
+    match text.strip_prefix(&[0xfe, 0xff]) {
+        Some(text) => String::from_utf16_lossy(
+            &text
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        None => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+/// Reads the Nero `chpl` atom.
+fn read_chpl(chpl: &[u8]) -> Result<Vec<Chapter>> {
+    // Version and flags, a reserved word in version 1, then the chapter count.
+    let mut data = chpl
+        .get(if chpl.first() == Some(&1) { 8 } else { 4 }..)
+        .unwrap_or_default();
+    let Some((&count, rest)) = data.split_first() else {
+        return Err(anyhow!("Truncated chpl atom"));
+    };
+    data = rest;
+
+    let mut chapters = vec![];
+    for _ in 0..count {
+        // Start in 100 ns units, then a length-prefixed title.
+        let Some(&len) = data.get(8) else { break };
+        let Some(title) = data.get(9..9 + len as usize) else {
+            break;
+        };
+        let start = u64::from_be_bytes(data[..8].try_into()?);
+        chapters.push(Chapter {
+            title: String::from_utf8_lossy(title).into_owned(),
+            start: Duration::from_nanos(start * 100),
+        });
+        data = &data[9 + len as usize..];
+    }
+    Ok(chapters)
+}
+
+/// Lists the name of each atom between `start` and `end` of an MP4 file, with where its
+/// contents start and end.
+fn atoms(file: &mut File, start: u64, end: u64) -> Result<Vec<([u8; 4], u64, u64)>> {
+    let mut atoms = vec![];
+    let mut offset = start;
+    while offset + 8 <= end {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let (mut size, mut header_len) = (u32::from_be_bytes(header[..4].try_into()?) as u64, 8);
+        if size == 1 {
+            let mut large = [0; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - offset;
+        }
+        if size < header_len {
+            return Err(anyhow!("Invalid atom size at {offset}"));
+        }
+
+        atoms.push((
+            header[4..8].try_into()?,
+            offset + header_len,
+            (offset + size).min(end),
+        ));
+        offset += size;
+    }
+    Ok(atoms)
+}
+
+/// Finds the contents of the atom at `path` between `start` and `end` of an MP4 file.
+fn find_atom(
+    file: &mut File,
+    start: u64,
+    end: u64,
+    path: &[&[u8; 4]],
+) -> Result<Option<(u64, u64)>> {
+    let Some((name, rest)) = path.split_first() else {
+        return Ok(Some((start, end)));
+    };
+    match atoms(file, start, end)?
+        .into_iter()
+        .find(|(atom, ..)| atom == *name)
+    {
+        Some((_, start, end)) => find_atom(file, start, end, rest),
+        None => Ok(None),
+    }
+}
+
+/// Reads the contents of an atom found with [`find_atom`].
+fn read_atom(file: &mut File, (start, end): (u64, u64)) -> Result<Vec<u8>> {
+    if end > file.metadata()?.len() {
+        return Err(anyhow!("Atom at {start} is past the end of the file"));
+    }
+    let mut data = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_be_bytes(
+        data.get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("Truncated atom"))?
+            .try_into()?,
+    ))
+}
+
+/// Reads `CHAPTER001=00:00:00.000` and `CHAPTER001NAME=...` comments.
+fn read_vorbis(tag: &Tag) -> Vec<Chapter> {
+    let mut starts = BTreeMap::new();
+    let mut titles = BTreeMap::new();
+    for item in tag.items() {
+        let (ItemKey::Unknown(key), Some(value)) = (item.key(), item.value().text()) else {
+            continue;
+        };
+        let key = key.to_ascii_uppercase();
+        let Some(number) = key.strip_prefix("CHAPTER") else {
+            continue;
+        };
+        match (number.get(..3), number.get(3..)) {
+            (Some(number), Some("")) => starts.insert(number.to_string(), value),
+            (Some(number), Some("NAME")) => titles.insert(number.to_string(), value),
+            _ => continue,
+        };
+    }
+
+    starts
+        .into_iter()
+        .filter_map(|(number, start)| {
+            Some(Chapter {
+                title: titles.get(&number).copied().unwrap_or_default().to_string(),
+                start: parse_time(start)?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `HH:MM:SS.mmm`.
+fn parse_time(time: &str) -> Option<Duration> {
+    let mut parts = time.rsplitn(3, ':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    let hours: u64 = parts.next().map_or(Ok(0), str::parse).ok()?;
+    Some(
+        Duration::from_secs(hours * 3600 + minutes * 60)
+            + Duration::try_from_secs_f64(seconds).ok()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{id3_frames, id3_text, mp4_text, parse_time};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse_time("01:02:03.500"),
+            Some(Duration::from_millis(3723500))
+        );
+        assert_eq!(parse_time("nope"), None);
+
+        assert_eq!(id3_text(b"\x03Intro\0"), "Intro");
+        assert_eq!(id3_text(b"\x01\xff\xfeH\0i\0"), "Hi");
+
+        let frames = b"TIT2\0\0\0\x03\0\0\x00Hi\0\0\0\0";
+        let frames: Vec<_> = id3_frames(frames, 4).collect();
+        assert_eq!(frames, [(&b"TIT2"[..], &b"\x00Hi"[..])]);
+
+        assert_eq!(mp4_text(b"\0\x05Intro\0\0\0\x0cencd"), "Intro");
+        assert_eq!(mp4_text(b"\0\x06\xfe\xff\0H\0i"), "Hi");
+    }
+}
@@ -0,0 +1,150 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use log::{debug, warn};
+use slint::{ComponentHandle, Model};
+
+use crate::audio::Audio;
+use crate::state::State;
+use crate::{MainWindow, NowPlaying, Song};
+
+/// How far into a chapter going back returns to its start rather than the previous chapter, in
+/// seconds.
+const RESTART_CHAPTER_SECS: i32 = 3;
+
+pub fn init(app: &MainWindow, audio: Rc<dyn Audio>) {
+    let now_playing = app.global::<NowPlaying>();
+
+    now_playing.on_seek_to_chapter({
+        let app = app.as_weak();
+        let audio = audio.clone();
+        move |index| seek_to(&app.unwrap(), audio.as_ref(), index.max(0) as usize)
+    });
+
+    now_playing.on_chapter_at({
+        let app = app.as_weak();
+        move |position| {
+            let song = app.unwrap().global::<NowPlaying>().get_song();
+            chapter_at(&song, position).map_or(-1, |index| index as i32)
+        }
+    });
+
+    now_playing.on_previous_chapter({
+        let app = app.as_weak();
+        let audio = audio.clone();
+        move || {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let (song, progress) = (now_playing.get_song(), now_playing.get_progress());
+            let Some(index) = chapter_at(&song, progress) else {
+                return;
+            };
+            let current = start(&song, index).as_secs() as i32;
+            if progress - current > RESTART_CHAPTER_SECS || index == 0 {
+                seek_to(&app, audio.as_ref(), index);
+            } else {
+                seek_to(&app, audio.as_ref(), index - 1);
+            }
+        }
+    });
+
+    now_playing.on_next_chapter({
+        let app = app.as_weak();
+        move || {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let song = now_playing.get_song();
+            let Some(index) = chapter_at(&song, now_playing.get_progress()) else {
+                return;
+            };
+            if index + 1 < song.chapters.row_count() {
+                seek_to(&app, audio.as_ref(), index + 1);
+            }
+        }
+    });
+}
+
+/// Whether `song` is an audiobook, whose position is remembered on its own.
+pub fn is_audiobook(song: &Song) -> bool {
+    let path = Path::new(song.path.as_str());
+    song.chapters.row_count() > 0
+        || path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("m4b"))
+}
+
+/// Remembers `progress` in `song` if it is an audiobook. Books that are back at the start are
+/// forgotten.
+pub fn bookmark(state: &RefCell<State>, song: &Song, progress: i32) {
+    if !is_audiobook(song) {
+        return;
+    }
+
+    let mut state = state.borrow_mut();
+    let path = song.path.to_string();
+    let changed = if progress > 0 {
+        state.bookmarks.insert(path, progress) != Some(progress)
+    } else {
+        state.bookmarks.remove(&path).is_some()
+    };
+    if changed {
+        if let Err(e) = state.save() {
+            warn!("Failed to save state: {}", e);
+        }
+    }
+}
+
+/// The position to resume `song` from, if it is a bookmarked audiobook.
+pub fn resume(state: &RefCell<State>, song: &Song) -> Option<i32> {
+    if !is_audiobook(song) {
+        return None;
+    }
+    state.borrow().bookmarks.get(song.path.as_str()).copied()
+}
+
+/// Where the chapter of `song` at `position` ends, in seconds: at the start of the next one, or
+/// at the end of the song.
+pub fn chapter_end(song: &Song, position: i32) -> i32 {
+    match chapter_at(song, position) {
+        Some(index) if index + 1 < song.chapters.row_count() => {
+            start(song, index + 1).as_secs_f32().ceil() as i32
+        }
+        _ => song.duration,
+    }
+}
+
+/// Seeks to the exact start of chapter `index` of the song playing.
+fn seek_to(app: &MainWindow, audio: &dyn Audio, index: usize) {
+    let now_playing = app.global::<NowPlaying>();
+    let position = start(&now_playing.get_song(), index);
+    debug!("chapter {} at {:?}", index, position);
+    now_playing.set_progress(position.as_secs() as i32);
+    if let Err(e) = audio.seek(position) {
+        warn!("Failed to seek: {}", e);
+    }
+}
+
+/// The index of the chapter of `song` at `position`, in seconds.
+fn chapter_at(song: &Song, position: i32) -> Option<usize> {
+    let chapters = song.chapters.iter().collect::<Vec<_>>();
+    match chapters
+        .iter()
+        .rposition(|chapter| chapter.start_ms / 1000 <= position)
+    {
+        Some(index) => Some(index),
+        // Before the first chapter.
+        None if !chapters.is_empty() => Some(0),
+        None => None,
+    }
+}
+
+/// Start of chapter `index` of `song`.
+fn start(song: &Song, index: usize) -> Duration {
+    song.chapters
+        .row_data(index)
+        .map_or(Duration::ZERO, |chapter| {
+            Duration::from_millis(chapter.start_ms.max(0) as u64)
+        })
+}
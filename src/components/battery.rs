@@ -0,0 +1,56 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use log::warn;
+use slint::{ComponentHandle, Timer, TimerMode};
+
+use crate::battery::Battery;
+use crate::components::now_playing;
+use crate::state::State;
+use crate::{BatteryModel, MainWindow};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// At or below this level, while discharging, the user is warned and the playback state is saved
+/// in case the device dies.
+const CRITICAL_LEVEL: u8 = 5;
+
+pub fn init(app: &MainWindow, state: Rc<RefCell<State>>) -> Option<Timer> {
+    let battery = Battery::open()
+        .map_err(|e| warn!("Failed to open battery: {}", e))
+        .ok()?;
+
+    let saved = Cell::new(false);
+    let update = {
+        let app = app.as_weak();
+        move || {
+            let status = match battery.status() {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("Failed to read battery: {}", e);
+                    return;
+                }
+            };
+
+            let app = app.unwrap();
+            let model = app.global::<BatteryModel>();
+            model.set_level(status.level as i32);
+            model.set_charging(status.charging);
+
+            let critical = status.level <= CRITICAL_LEVEL && !status.charging;
+            model.set_critical(critical);
+            if critical && !saved.replace(true) {
+                warn!("battery critical: {}%", status.level);
+                now_playing::save_state(&app, &state);
+            } else if !critical {
+                saved.set(false);
+            }
+        }
+    };
+    update();
+
+    let timer = Timer::default();
+    timer.start(TimerMode::Repeated, POLL_INTERVAL, update);
+    Some(timer)
+}
@@ -0,0 +1,154 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use log::{debug, warn};
+use slint::{ComponentHandle, ModelRc, VecModel};
+
+use crate::audio::dsp::DspSettings;
+use crate::audio::eq::{builtin_presets, Band, Preset, MAX_GAIN};
+use crate::audio::Audio;
+use crate::config::EqConfig;
+use crate::state::State;
+use crate::{EqBand, EqualizerModel, MainWindow};
+
+pub fn init(
+    app: &MainWindow,
+    config: &EqConfig,
+    state: Rc<RefCell<State>>,
+    dsp: Rc<RefCell<DspSettings>>,
+    audio: Rc<dyn Audio>,
+) {
+    let presets: Rc<Vec<Preset>> = Rc::new(
+        builtin_presets()
+            .into_iter()
+            .chain(config.presets.iter().cloned())
+            .collect(),
+    );
+
+    let equalizer = app.global::<EqualizerModel>();
+    equalizer.set_max_gain(MAX_GAIN);
+    apply(&presets, &state.borrow(), &dsp, audio.as_ref());
+    refresh(app, &presets, &state.borrow());
+
+    equalizer.on_refresh({
+        let app = app.as_weak();
+        let presets = presets.clone();
+        let state = state.clone();
+        move || refresh(&app.unwrap(), &presets, &state.borrow())
+    });
+
+    equalizer.on_cycle_preset({
+        let app = app.as_weak();
+        let presets = presets.clone();
+        let state = state.clone();
+        let dsp = dsp.clone();
+        let audio = audio.clone();
+        move |step| {
+            let mut state = state.borrow_mut();
+            let current = presets
+                .iter()
+                .position(|preset| preset.name == state.equalizer.preset)
+                .unwrap_or(0);
+            let next = (current as i32 + step).rem_euclid(presets.len() as i32) as usize;
+            debug!("equalizer preset {}", presets[next].name);
+
+            state.equalizer.preset = presets[next].name.clone();
+            state.equalizer.gains = presets[next].bands.iter().map(|band| band.gain).collect();
+            save(&mut state, &presets, &dsp, audio.as_ref());
+            refresh(&app.unwrap(), &presets, &state);
+        }
+    });
+
+    equalizer.on_set_gain({
+        let app = app.as_weak();
+        move |index, gain| {
+            let gain = gain.round().clamp(-MAX_GAIN, MAX_GAIN);
+            let mut state = state.borrow_mut();
+            match index {
+                0 => state.equalizer.bass = gain,
+                1 => state.equalizer.treble = gain,
+                _ => {
+                    let current = preset(&presets, &state);
+                    if state.equalizer.gains.len() != current.bands.len() {
+                        state.equalizer.gains =
+                            current.bands.iter().map(|band| band.gain).collect();
+                    }
+                    if let Some(band) = state.equalizer.gains.get_mut(index as usize - 2) {
+                        *band = gain;
+                    }
+                }
+            }
+            save(&mut state, &presets, &dsp, audio.as_ref());
+            refresh(&app.unwrap(), &presets, &state);
+        }
+    });
+}
+
+/// The chosen preset, or the first one if it no longer exists.
+fn preset<'a>(presets: &'a [Preset], state: &State) -> &'a Preset {
+    presets
+        .iter()
+        .find(|preset| preset.name == state.equalizer.preset)
+        .unwrap_or(&presets[0])
+}
+
+/// The bands of the chosen preset with the adjusted gains, followed by the bass and treble
+/// shelves.
+fn bands(presets: &[Preset], state: &State) -> Vec<Band> {
+    let mut bands = preset(presets, state).bands.clone();
+    if state.equalizer.gains.len() == bands.len() {
+        for (band, &gain) in bands.iter_mut().zip(&state.equalizer.gains) {
+            band.gain = gain;
+        }
+    }
+    bands.push(Band::bass(state.equalizer.bass));
+    bands.push(Band::treble(state.equalizer.treble));
+    bands
+}
+
+fn apply(presets: &[Preset], state: &State, dsp: &RefCell<DspSettings>, audio: &dyn Audio) {
+    dsp.borrow_mut().equalizer = bands(presets, state);
+    if let Err(e) = audio.set_dsp(dsp.borrow().clone()) {
+        warn!("Failed to set equalizer: {}", e);
+    }
+}
+
+fn save(state: &mut State, presets: &[Preset], dsp: &RefCell<DspSettings>, audio: &dyn Audio) {
+    apply(presets, state, dsp, audio);
+    if let Err(e) = state.save() {
+        warn!("Failed to save state: {}", e);
+    }
+}
+
+fn refresh(app: &MainWindow, presets: &[Preset], state: &State) {
+    let equalizer = app.global::<EqualizerModel>();
+    equalizer.set_preset(preset(presets, state).name.as_str().into());
+
+    let bands = bands(presets, state);
+    let (peaks, shelves) = bands.split_at(bands.len() - 2);
+    let rows: Vec<EqBand> = [
+        EqBand {
+            label: "Bass".into(),
+            gain: shelves[0].gain,
+        },
+        EqBand {
+            label: "Treble".into(),
+            gain: shelves[1].gain,
+        },
+    ]
+    .into_iter()
+    .chain(peaks.iter().map(|band| EqBand {
+        label: label(band.frequency).into(),
+        gain: band.gain,
+    }))
+    .collect();
+    equalizer.set_bands(ModelRc::new(VecModel::from(rows)));
+}
+
+fn label(frequency: f32) -> String {
+    if frequency >= 1000.0 {
+        format!("{} kHz", frequency / 1000.0)
+    } else {
+        format!("{} Hz", frequency)
+    }
+}
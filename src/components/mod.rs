@@ -1,7 +1,72 @@
+pub mod audiobook;
+pub mod battery;
+pub mod equalizer;
 pub mod now_playing;
+pub mod settings;
+pub mod sleep;
+pub mod speed;
+pub mod stereo;
 
-use crate::MainWindow;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-pub fn init(app: &MainWindow) {
-    now_playing::init(app);
+use slint::{ComponentHandle, Timer};
+
+use crate::audio::dsp::DspSettings;
+use crate::audio::Audio;
+use crate::config::Config;
+use crate::state::State;
+use crate::{Format, MainWindow};
+
+/// Sets up the UI callbacks. The returned timers must be kept alive for as long as the app runs.
+/// `lock_screen` turns the screen off and `set_brightness` sets the backlight, on platforms that
+/// can.
+pub fn init(
+    app: &MainWindow,
+    config: &Config,
+    state: Rc<RefCell<State>>,
+    audio: Rc<dyn Audio>,
+    lock_screen: Option<Box<dyn Fn()>>,
+    set_brightness: Option<Box<dyn Fn(u8)>>,
+) -> Vec<Timer> {
+    init_format(app);
+
+    // Settings of the DSP chain, shared by the screens that change them.
+    let dsp = Rc::new(RefCell::new(DspSettings::default()));
+    now_playing::init(
+        app,
+        &config.player,
+        state.clone(),
+        dsp.clone(),
+        audio.clone(),
+    );
+    audiobook::init(app, audio.clone());
+    speed::init(app, state.clone(), dsp.clone(), audio.clone());
+    equalizer::init(
+        app,
+        &config.equalizer,
+        state.clone(),
+        dsp.clone(),
+        audio.clone(),
+    );
+    stereo::init(
+        app,
+        &config.stereo,
+        state.clone(),
+        dsp.clone(),
+        audio.clone(),
+    );
+    sleep::init(app, &config.sleep, state.clone(), dsp, audio, lock_screen);
+    settings::init(app, state.clone(), set_brightness);
+
+    battery::init(app, state).into_iter().collect()
+}
+
+/// Sets up the formatting helpers used by the views.
+pub fn init_format(app: &MainWindow) {
+    app.global::<Format>().on_format_time(|seconds: i32| {
+        let minutes = seconds / 60;
+        let seconds = seconds % 60;
+        format!("{minutes:02}:{seconds:02}").into()
+    });
 }
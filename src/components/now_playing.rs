@@ -1,33 +1,255 @@
-use std::path::Path;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use log::debug;
-use slint::ComponentHandle;
+use log::{debug, info, warn};
+use slint::{ComponentHandle, Model};
 
-use crate::audio::{Audio, AUDIO};
-use crate::{MainWindow, NowPlaying};
+use crate::audio::dsp::DspSettings;
+use crate::audio::{Audio, Event};
+use crate::components::{audiobook, sleep, speed};
+use crate::config::PlayerConfig;
+use crate::song::SongData;
+use crate::state::State;
+use crate::{LibraryModel, MainWindow, NowPlaying, Song};
 
-pub fn init(app: &MainWindow) {
+/// Presses of a seek key closer together than this count as holding it down.
+const HOLD_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn init(
+    app: &MainWindow,
+    config: &PlayerConfig,
+    state: Rc<RefCell<State>>,
+    dsp: Rc<RefCell<DspSettings>>,
+    audio: Rc<dyn Audio>,
+) {
     let now_playing = app.global::<NowPlaying>();
 
     now_playing.set_is_playing(true);
+    listen(app, audio.events());
+
+    now_playing.on_load_song({
+        let app = app.as_weak();
+        let dsp = dsp.clone();
+        let audio = audio.clone();
+        move |song| {
+            debug!("load");
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            audiobook::bookmark(&state, &now_playing.get_song(), now_playing.get_progress());
 
-    now_playing.on_load_song(|song| {
-        debug!("load");
-        let _ = AUDIO.load(Path::new(song.path.as_str()));
+            speed::restore(&app, &state.borrow(), &dsp, audio.as_ref(), &song);
+            let _ = audio.load(Path::new(song.path.as_str()));
+            let resume = audiobook::resume(&state, &song);
+            now_playing.set_song(song);
+            now_playing.set_progress(0);
+            if let Some(progress) = resume {
+                debug!("resume at {}", progress);
+                now_playing.invoke_seek(progress);
+                now_playing.set_progress(progress);
+            }
+        }
     });
 
-    now_playing.on_play(|| {
-        debug!("play");
-        let _ = AUDIO.play();
+    now_playing.on_play({
+        let audio = audio.clone();
+        move || {
+            debug!("play");
+            sleep::restore_volume(&dsp, audio.as_ref());
+            let _ = audio.play();
+        }
     });
 
-    now_playing.on_pause(|| {
-        debug!("pause");
-        let _ = AUDIO.pause();
+    now_playing.on_pause({
+        let audio = audio.clone();
+        move || {
+            debug!("pause");
+            let _ = audio.pause();
+        }
     });
 
-    now_playing.on_seek(|duration| {
+    now_playing.on_seek(move |duration| {
         debug!("seek {}", duration);
-        let _ = AUDIO.seek(duration);
+        let _ = audio.seek(Duration::from_secs(duration.max(0) as u64));
+    });
+
+    let hold = Rc::new(RefCell::new(SeekHold::new(config)));
+
+    now_playing.on_seek_step({
+        let app = app.as_weak();
+        let hold = hold.clone();
+        move |direction| {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let step = hold.borrow_mut().step(direction);
+            let progress =
+                (now_playing.get_progress() + step).clamp(0, now_playing.get_song().duration);
+            now_playing.set_progress(progress);
+            now_playing.invoke_seek(progress);
+        }
+    });
+
+    now_playing.on_scrub({
+        let app = app.as_weak();
+        move |direction| {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            if !now_playing.get_is_scrubbing() {
+                now_playing.set_scrub_position(now_playing.get_progress());
+                now_playing.set_is_scrubbing(true);
+            }
+            let step = hold.borrow_mut().step(direction);
+            let position =
+                (now_playing.get_scrub_position() + step).clamp(0, now_playing.get_song().duration);
+            now_playing.set_scrub_position(position);
+        }
+    });
+
+    now_playing.on_commit_scrub({
+        let app = app.as_weak();
+        move || {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let position = now_playing.get_scrub_position();
+            debug!("scrub to {}", position);
+            now_playing.set_is_scrubbing(false);
+            now_playing.set_progress(position);
+            now_playing.invoke_seek(position);
+        }
     });
 }
+
+/// A seek key being pressed repeatedly or held down. The step doubles for every second it is
+/// held, up to the configured maximum.
+struct SeekHold {
+    base: u32,
+    max: u32,
+    direction: i32,
+    since: Instant,
+    last: Instant,
+}
+
+impl SeekHold {
+    fn new(config: &PlayerConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            base: config.seek_step_secs,
+            max: config.seek_max_step_secs.max(config.seek_step_secs),
+            direction: 0,
+            since: now,
+            last: now,
+        }
+    }
+
+    /// Returns the seconds to move for a press in `direction`, negative for backward.
+    fn step(&mut self, direction: i32) -> i32 {
+        let now = Instant::now();
+        if direction != self.direction || now - self.last > HOLD_INTERVAL {
+            self.direction = direction;
+            self.since = now;
+        }
+        self.last = now;
+
+        let held = (now - self.since).as_secs().min(16) as u32;
+        let step = self.base.saturating_mul(1 << held).min(self.max);
+        direction.signum() * step as i32
+    }
+}
+
+/// Follows the playback events on a background thread, moving on to the next song when one
+/// finishes or cannot be played.
+fn listen(app: &MainWindow, events: kanal::Receiver<Event>) {
+    let app = app.as_weak();
+    thread::spawn(move || {
+        while let Ok(event) = events.recv() {
+            if app
+                .upgrade_in_event_loop(move |app| handle_event(&app, event))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+fn handle_event(app: &MainWindow, event: Event) {
+    let now_playing = app.global::<NowPlaying>();
+    match event {
+        Event::Finished => {
+            // Played to the end, so a book starts over next time.
+            now_playing.set_progress(0);
+            // Pause for the sleep timer before the next track can be heard.
+            if now_playing.invoke_sleep_at_track_end() {
+                return;
+            }
+            if now_playing.get_repeat() {
+                now_playing.invoke_load_song(now_playing.get_song());
+                return;
+            }
+        }
+        Event::Failed(e) => warn!("Skipping {}: {}", now_playing.get_song().path, e),
+        Event::Error(e) => {
+            warn!("Audio output failed: {}", e);
+            now_playing.set_is_playing(false);
+            return;
+        }
+        Event::Seeked(position) => {
+            now_playing.set_progress(position.as_secs() as i32);
+            return;
+        }
+    }
+
+    match next_song(app) {
+        Some(song) => now_playing.invoke_load_song(song),
+        None => {
+            info!("end of library");
+            now_playing.set_is_playing(false);
+            now_playing.invoke_pause();
+        }
+    }
+}
+
+/// The song after the current one in the library, if any.
+fn next_song(app: &MainWindow) -> Option<Song> {
+    let songs = app.global::<LibraryModel>().get_songs();
+    let path = app.global::<NowPlaying>().get_song().path;
+    let index = songs.iter().position(|song| song.path == path)?;
+    songs.row_data(index + 1)
+}
+
+/// Remembers the current song and position, so playback can resume on the next start.
+pub fn save_state(app: &MainWindow, state: &RefCell<State>) {
+    let now_playing = app.global::<NowPlaying>();
+    let song = now_playing.get_song();
+    audiobook::bookmark(state, &song, now_playing.get_progress());
+
+    let mut state = state.borrow_mut();
+    state.song = (!song.path.is_empty()).then(|| PathBuf::from(song.path.as_str()));
+    state.progress = now_playing.get_progress();
+    if let Err(e) = state.save() {
+        warn!("Failed to save state: {}", e);
+    }
+}
+
+/// Resumes the song that was playing when the state was last saved.
+pub fn restore_state(app: &MainWindow, state: &RefCell<State>) {
+    let (path, progress) = {
+        let state = state.borrow();
+        (state.song.clone(), state.progress)
+    };
+    let Some(path) = path else {
+        return;
+    };
+
+    match SongData::load(path) {
+        Ok(song) => {
+            let now_playing = app.global::<NowPlaying>();
+            now_playing.invoke_load_song((&song).into());
+            now_playing.invoke_seek(progress);
+            now_playing.set_progress(progress);
+        }
+        Err(e) => warn!("Failed to restore song: {}", e),
+    }
+}
@@ -0,0 +1,48 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use log::{debug, warn};
+use slint::ComponentHandle;
+
+use crate::state::{State, BRIGHTNESS_STEP, MIN_BRIGHTNESS};
+use crate::{MainWindow, SettingsModel};
+
+/// `set_brightness` changes the backlight, which the platform owns.
+pub fn init(app: &MainWindow, state: Rc<RefCell<State>>, set_brightness: Option<Box<dyn Fn(u8)>>) {
+    let settings = app.global::<SettingsModel>();
+
+    settings.set_brightness(state.borrow().brightness as i32);
+    settings.set_brightness_step(BRIGHTNESS_STEP as i32);
+
+    settings.on_refresh({
+        let app = app.as_weak();
+        let state = state.clone();
+        move || {
+            let app = app.unwrap();
+            app.global::<SettingsModel>()
+                .set_brightness(state.borrow().brightness as i32);
+        }
+    });
+
+    settings.on_set_brightness({
+        let app = app.as_weak();
+        move |brightness| {
+            let brightness = brightness.clamp(MIN_BRIGHTNESS as i32, 100) as u8;
+            debug!("set brightness {}", brightness);
+
+            if let Some(ref set_brightness) = set_brightness {
+                set_brightness(brightness);
+            }
+
+            let mut state = state.borrow_mut();
+            state.brightness = brightness;
+            if let Err(e) = state.save() {
+                warn!("Failed to save state: {}", e);
+            }
+
+            let app = app.unwrap();
+            app.global::<SettingsModel>()
+                .set_brightness(brightness as i32);
+        }
+    });
+}
@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::process::Command;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use slint::{ComponentHandle, SharedString, Timer, TimerMode};
+
+use crate::audio::dsp::DspSettings;
+use crate::audio::Audio;
+use crate::components::{audiobook, now_playing};
+use crate::config::{SleepAction, SleepConfig};
+use crate::state::State;
+use crate::{MainWindow, NowPlaying};
+
+/// When playback should stop.
+enum Deadline {
+    /// After a fixed time.
+    At(Instant),
+    /// When `path` has played up to `position`, in seconds, or is no longer playing.
+    End { path: SharedString, position: i32 },
+}
+
+struct SleepTimer {
+    /// Index of the chosen entry of the config's minutes, or one past them for the end of the
+    /// track. `None` while off.
+    choice: Option<usize>,
+    deadline: Option<Deadline>,
+    timer: Timer,
+}
+
+pub fn init(
+    app: &MainWindow,
+    config: &SleepConfig,
+    state: Rc<RefCell<State>>,
+    dsp: Rc<RefCell<DspSettings>>,
+    audio: Rc<dyn Audio>,
+    lock_screen: Option<Box<dyn Fn()>>,
+) {
+    let sleep = Rc::new(RefCell::new(SleepTimer {
+        choice: None,
+        deadline: None,
+        timer: Timer::default(),
+    }));
+    let config = Rc::new(config.clone());
+    let lock_screen: Rc<dyn Fn()> = match lock_screen {
+        Some(lock_screen) => Rc::from(lock_screen),
+        None => Rc::new(|| warn!("Can't lock the screen here")),
+    };
+
+    app.global::<NowPlaying>().on_sleep_at_track_end({
+        let app = app.as_weak();
+        let sleep = sleep.clone();
+        let config = config.clone();
+        let state = state.clone();
+        let audio = audio.clone();
+        let lock_screen = lock_screen.clone();
+        move || {
+            let app = app.unwrap();
+            let path = app.global::<NowPlaying>().get_song().path;
+            let at_end = matches!(
+                sleep.borrow().deadline,
+                Some(Deadline::End { path: ref end, .. }) if *end == path
+            );
+            if at_end {
+                sleep.borrow_mut().cancel(&app);
+                run_out(&app, &config, &state, audio.as_ref(), lock_screen.as_ref());
+            }
+            at_end
+        }
+    });
+
+    app.global::<NowPlaying>().on_cycle_sleep_timer({
+        let app = app.as_weak();
+        let sleep = sleep.clone();
+        move || {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let mut timer = sleep.borrow_mut();
+            timer.choice = match timer.choice {
+                None => Some(0),
+                Some(choice) if choice < config.minutes.len() => Some(choice + 1),
+                Some(_) => None,
+            };
+            timer.deadline = timer.choice.map(|choice| match config.minutes.get(choice) {
+                Some(&minutes) => {
+                    Deadline::At(Instant::now() + Duration::from_secs(minutes as u64 * 60))
+                }
+                None => {
+                    let song = now_playing.get_song();
+                    let position = audiobook::chapter_end(&song, now_playing.get_progress());
+                    Deadline::End {
+                        path: song.path,
+                        position,
+                    }
+                }
+            });
+            set_volume(&dsp, audio.as_ref(), 1.0);
+
+            if timer.deadline.is_none() {
+                debug!("sleep timer off");
+                timer.cancel(&app);
+                return;
+            }
+            now_playing.set_sleep_at_end(matches!(timer.deadline, Some(Deadline::End { .. })));
+            debug!(
+                "sleep timer at {:?} minutes",
+                timer.choice.and_then(|choice| config.minutes.get(choice))
+            );
+
+            let tick = {
+                let app = app.as_weak();
+                let sleep = sleep.clone();
+                let config = config.clone();
+                let state = state.clone();
+                let dsp = dsp.clone();
+                let audio = audio.clone();
+                let lock_screen = lock_screen.clone();
+                move || {
+                    let app = app.unwrap();
+                    let Some(remaining) = sleep.borrow().remaining(&app) else {
+                        return;
+                    };
+                    app.global::<NowPlaying>()
+                        .set_sleep_remaining(remaining.ceil() as i32);
+
+                    let fade = config.fade_secs as f32;
+                    if remaining <= 0.0 {
+                        sleep.borrow_mut().cancel(&app);
+                        run_out(&app, &config, &state, audio.as_ref(), lock_screen.as_ref());
+                    } else if remaining < fade {
+                        // Squared, as loudness is heard logarithmically.
+                        set_volume(&dsp, audio.as_ref(), (remaining / fade).powi(2));
+                    }
+                }
+            };
+            timer
+                .timer
+                .start(TimerMode::Repeated, Duration::from_secs(1), tick);
+            now_playing.set_sleep_remaining(
+                timer
+                    .remaining(&app)
+                    .map_or(-1, |remaining| remaining.ceil() as i32),
+            );
+        }
+    });
+}
+
+impl SleepTimer {
+    /// Seconds of playback left before the timer runs out.
+    fn remaining(&self, app: &MainWindow) -> Option<f32> {
+        let now_playing = app.global::<NowPlaying>();
+        Some(match self.deadline.as_ref()? {
+            Deadline::At(deadline) => deadline
+                .saturating_duration_since(Instant::now())
+                .as_secs_f32(),
+            Deadline::End { path, position } => {
+                if now_playing.get_song().path != *path {
+                    0.0
+                } else {
+                    (position - now_playing.get_progress()).max(0) as f32 / now_playing.get_speed()
+                }
+            }
+        })
+    }
+
+    fn cancel(&mut self, app: &MainWindow) {
+        self.choice = None;
+        self.deadline = None;
+        self.timer.stop();
+        let now_playing = app.global::<NowPlaying>();
+        now_playing.set_sleep_remaining(-1);
+        now_playing.set_sleep_at_end(false);
+    }
+}
+
+/// Pauses playback, then does the configured action.
+fn run_out(
+    app: &MainWindow,
+    config: &SleepConfig,
+    state: &RefCell<State>,
+    audio: &dyn Audio,
+    lock_screen: &dyn Fn(),
+) {
+    info!("sleep timer ran out");
+    let now_playing = app.global::<NowPlaying>();
+    now_playing.set_is_playing(false);
+    now_playing.invoke_pause();
+    // The pause fades out from here, so the volume stays down until playback resumes.
+    now_playing::save_state(app, state);
+
+    match config.action {
+        SleepAction::Pause => {}
+        SleepAction::Lock => lock_screen(),
+        SleepAction::Shutdown => {
+            info!("shutting down");
+            if let Err(e) = audio.stop() {
+                warn!("Failed to stop audio: {}", e);
+            }
+            if let Err(e) = Command::new("poweroff").status() {
+                warn!("Failed to shut down: {}", e);
+            }
+        }
+    }
+}
+
+/// Brings back the volume a sleep timer faded out. Called before playback resumes, as raising it
+/// while the pause is still fading out would be heard.
+pub fn restore_volume(dsp: &RefCell<DspSettings>, audio: &dyn Audio) {
+    set_volume(dsp, audio, 1.0);
+}
+
+fn set_volume(dsp: &RefCell<DspSettings>, audio: &dyn Audio, volume: f32) {
+    if dsp.borrow().volume == volume {
+        return;
+    }
+    dsp.borrow_mut().volume = volume;
+    if let Err(e) = audio.set_dsp(dsp.borrow().clone()) {
+        warn!("Failed to set volume: {}", e);
+    }
+}
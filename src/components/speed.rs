@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use log::{debug, warn};
+use slint::ComponentHandle;
+
+use crate::audio::dsp::DspSettings;
+use crate::audio::stretch::{MAX_SPEED, MIN_SPEED};
+use crate::audio::Audio;
+use crate::state::State;
+use crate::{MainWindow, NowPlaying, Song};
+
+/// How much a press of L2/R2 changes the speed.
+const SPEED_STEP: f32 = 0.25;
+
+pub fn init(
+    app: &MainWindow,
+    state: Rc<RefCell<State>>,
+    dsp: Rc<RefCell<DspSettings>>,
+    audio: Rc<dyn Audio>,
+) {
+    app.global::<NowPlaying>().on_change_speed({
+        let app = app.as_weak();
+        move |step| {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let speed =
+                (now_playing.get_speed() + step as f32 * SPEED_STEP).clamp(MIN_SPEED, MAX_SPEED);
+            debug!("speed {}", speed);
+            set(&app, speed, &dsp, audio.as_ref());
+
+            let mut state = state.borrow_mut();
+            let key = key(&now_playing.get_song());
+            if speed == 1.0 {
+                state.speeds.remove(&key);
+            } else {
+                state.speeds.insert(key, speed);
+            }
+            if let Err(e) = state.save() {
+                warn!("Failed to save state: {}", e);
+            }
+        }
+    });
+}
+
+/// Switches to the speed remembered for `song`.
+pub fn restore(
+    app: &MainWindow,
+    state: &State,
+    dsp: &RefCell<DspSettings>,
+    audio: &dyn Audio,
+    song: &Song,
+) {
+    let speed = state.speeds.get(&key(song)).copied().unwrap_or(1.0);
+    if speed != dsp.borrow().speed {
+        set(app, speed, dsp, audio);
+    }
+}
+
+fn set(app: &MainWindow, speed: f32, dsp: &RefCell<DspSettings>, audio: &dyn Audio) {
+    app.global::<NowPlaying>().set_speed(speed);
+    dsp.borrow_mut().speed = speed;
+    if let Err(e) = audio.set_dsp(dsp.borrow().clone()) {
+        warn!("Failed to set speed: {}", e);
+    }
+}
+
+/// Speeds are remembered per album, so that the chapters of a book or the episodes of a podcast
+/// share one.
+fn key(song: &Song) -> String {
+    if song.album.is_empty() {
+        song.path.to_string()
+    } else {
+        song.album.to_string()
+    }
+}
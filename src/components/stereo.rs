@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use log::{debug, warn};
+use slint::ComponentHandle;
+
+use crate::audio::dsp::DspSettings;
+use crate::audio::Audio;
+use crate::config::StereoConfig;
+use crate::state::State;
+use crate::{MainWindow, NowPlaying};
+
+pub fn init(
+    app: &MainWindow,
+    config: &StereoConfig,
+    state: Rc<RefCell<State>>,
+    dsp: Rc<RefCell<DspSettings>>,
+    audio: Rc<dyn Audio>,
+) {
+    let crossfeed = state.borrow().crossfeed.unwrap_or(config.crossfeed);
+    {
+        let mut dsp = dsp.borrow_mut();
+        dsp.width = config.width;
+        dsp.balance = config.balance;
+    }
+    apply(config, crossfeed, &dsp, audio.as_ref());
+
+    let now_playing = app.global::<NowPlaying>();
+    now_playing.set_crossfeed(crossfeed);
+
+    let config = config.clone();
+    now_playing.on_toggle_crossfeed({
+        let app = app.as_weak();
+        move || {
+            let app = app.unwrap();
+            let now_playing = app.global::<NowPlaying>();
+            let crossfeed = !now_playing.get_crossfeed();
+            debug!("crossfeed {}", crossfeed);
+            now_playing.set_crossfeed(crossfeed);
+            apply(&config, crossfeed, &dsp, audio.as_ref());
+
+            let mut state = state.borrow_mut();
+            state.crossfeed = Some(crossfeed);
+            if let Err(e) = state.save() {
+                warn!("Failed to save state: {}", e);
+            }
+        }
+    });
+}
+
+fn apply(config: &StereoConfig, crossfeed: bool, dsp: &RefCell<DspSettings>, audio: &dyn Audio) {
+    dsp.borrow_mut().crossfeed =
+        crossfeed.then_some((config.crossfeed_cutoff, config.crossfeed_level));
+    if let Err(e) = audio.set_dsp(dsp.borrow().clone()) {
+        warn!("Failed to set crossfeed: {}", e);
+    }
+}
@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use log::info;
+use serde::Deserialize;
+
+use crate::audio::eq::Preset;
+
+/// User configuration, read from `config.toml` at startup. Every field has a default, so a
+/// missing file or a partial file is fine.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub screen: ScreenConfig,
+    pub power: PowerConfig,
+    pub audio: AudioConfig,
+    pub player: PlayerConfig,
+    pub equalizer: EqConfig,
+    pub stereo: StereoConfig,
+    pub sleep: SleepConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ScreenConfig {
+    /// Clockwise rotation of the display, in degrees: 0, 90, 180 or 270.
+    pub rotation: u16,
+    /// Seconds without input before the screen dims. `0` disables dimming.
+    pub dim_secs: u64,
+    /// Brightness while dimmed, in percent.
+    pub dim_brightness: u8,
+    /// Seconds without input before the screen locks itself. `0` disables auto-lock.
+    pub auto_lock_secs: u64,
+}
+
+impl Default for ScreenConfig {
+    fn default() -> Self {
+        Self {
+            // The Miyoo Mini's panel is mounted upside down.
+            rotation: 180,
+            dim_secs: 30,
+            dim_brightness: 10,
+            auto_lock_secs: 60,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            info!("no config at {}, using defaults", path.display());
+            return Ok(Self::default());
+        }
+
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    /// What holding the power button does.
+    pub long_press: PowerAction,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerAction {
+    /// Save state and exit, returning to the frontend that launched us.
+    #[default]
+    Quit,
+    /// Put the device to sleep until the power button is pressed again.
+    Suspend,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// How songs are converted to the sample rate of the sound device.
+    pub resampler: ResamplerKind,
+    /// Length of the sinc filter for the `sinc` resampler. Longer is more accurate but slower.
+    pub sinc_len: usize,
+    /// Ask the sound device for the sample rate of each song, so that it is played bit-perfect
+    /// when the device supports it.
+    pub passthrough: bool,
+    /// Length of the fades on pause, resume, seek and stop, in ms, so that they don't click.
+    /// `0` cuts off at once.
+    pub fade_ms: u32,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            resampler: ResamplerKind::default(),
+            sinc_len: 128,
+            passthrough: false,
+            fade_ms: 20,
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResamplerKind {
+    /// Linear interpolation. Cheapest, with audible aliasing.
+    Linear,
+    /// Cubic interpolation. Cheap, and good enough for the built-in speaker.
+    #[default]
+    Fast,
+    /// Windowed sinc interpolation, with `sinc_len` taps.
+    Sinc,
+    /// FFT-based synchronous resampling. Best quality, but too heavy on the Miyoo's CPU to
+    /// leave room for the DSP chain.
+    Fft,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PlayerConfig {
+    /// Seconds skipped by a press of left/right, or each step while scrubbing with L/R.
+    pub seek_step_secs: u32,
+    /// Largest step, in seconds, that holding a seek key accelerates to.
+    pub seek_max_step_secs: u32,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            seek_step_secs: 10,
+            seek_max_step_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct EqConfig {
+    /// User presets, listed after the built-in ones:
+    ///
+    /// ```toml
+    /// [[equalizer.presets]]
+    /// name = "My Earbuds"
+    /// bands = [
+    ///     { kind = "lowshelf", frequency = 100, gain = 6, q = 0.7 },
+    ///     { kind = "peak", frequency = 3000, gain = -2, q = 2 },
+    /// ]
+    /// ```
+    pub presets: Vec<Preset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StereoConfig {
+    /// Whether headphone crossfeed is on at first start. It is toggled with B in the player.
+    pub crossfeed: bool,
+    /// Frequency below which the channels are crossfed, in Hz.
+    pub crossfeed_cutoff: f32,
+    /// Level of the crossfed signal below the direct one, in dB.
+    pub crossfeed_level: f32,
+    /// Left/right balance, from -1 for left only to 1 for right only.
+    pub balance: f32,
+    /// Stereo width: 0 is mono, 1 unchanged, above 1 wider.
+    pub width: f32,
+}
+
+impl Default for StereoConfig {
+    fn default() -> Self {
+        Self {
+            crossfeed: false,
+            crossfeed_cutoff: 700.0,
+            crossfeed_level: 4.5,
+            balance: 0.0,
+            width: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SleepConfig {
+    /// Times the sleep timer cycles through with Menu in the player, in minutes. After them
+    /// comes the end of the track, or of the chapter in audiobooks.
+    pub minutes: Vec<u32>,
+    /// Seconds over which playback fades out before the timer runs out.
+    pub fade_secs: u32,
+    /// What happens once playback is paused.
+    pub action: SleepAction,
+}
+
+impl Default for SleepConfig {
+    fn default() -> Self {
+        Self {
+            minutes: vec![15, 30, 45, 60, 90],
+            fade_secs: 30,
+            action: SleepAction::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SleepAction {
+    /// Only pause playback.
+    #[default]
+    Pause,
+    /// Also lock the screen, turning it off.
+    Lock,
+    /// Also turn the device off.
+    Shutdown,
+}
@@ -0,0 +1,194 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use image::RgbImage;
+use rgb::ComponentBytes;
+use slint::platform::software_renderer::{MinimalSoftwareWindow, RepaintBufferType};
+use slint::platform::{Platform, WindowAdapter, WindowEvent};
+use slint::{PhysicalSize, Rgb8Pixel, SharedString};
+
+use crate::input::Key;
+
+/// A platform without a screen or an event loop. The window is rendered with the software
+/// renderer into memory, and time only moves forward when told to, so that renders are
+/// reproducible.
+pub struct HeadlessPlatform {
+    window: Rc<MinimalSoftwareWindow>,
+    time: Rc<Cell<Duration>>,
+}
+
+impl Platform for HeadlessPlatform {
+    fn create_window_adapter(
+        &self,
+    ) -> Result<Rc<dyn slint::platform::WindowAdapter>, slint::PlatformError> {
+        Ok(self.window.clone())
+    }
+
+    fn duration_since_start(&self) -> Duration {
+        self.time.get()
+    }
+}
+
+/// Handle to the headless platform of the current thread.
+pub struct Headless {
+    window: Rc<MinimalSoftwareWindow>,
+    time: Rc<Cell<Duration>>,
+}
+
+impl Headless {
+    /// Installs the headless platform for the current thread.
+    pub fn init(width: u32, height: u32) -> Self {
+        let window = MinimalSoftwareWindow::new(RepaintBufferType::NewBuffer);
+        window.set_size(PhysicalSize::new(width, height));
+        let time = Rc::new(Cell::new(Duration::ZERO));
+
+        slint::platform::set_platform(Box::new(HeadlessPlatform {
+            window: window.clone(),
+            time: time.clone(),
+        }))
+        .unwrap();
+
+        Self { window, time }
+    }
+
+    /// Moves the clock forward, running any timers and animations that are due.
+    pub fn advance(&self, duration: Duration) {
+        self.time.set(self.time.get() + duration);
+        slint::platform::update_timers_and_animations();
+    }
+
+    /// Taps a key.
+    pub fn press(&self, key: Key) {
+        let text: SharedString = Option::from(key).unwrap();
+        self.window
+            .dispatch_event(WindowEvent::KeyPressed { text: text.clone() });
+        self.window
+            .dispatch_event(WindowEvent::KeyReleased { text });
+        slint::platform::update_timers_and_animations();
+    }
+
+    /// Renders the whole window, after letting animations settle.
+    pub fn render(&self) -> RgbImage {
+        self.advance(Duration::from_secs(5));
+
+        let size = self.window.size();
+        let mut buffer = vec![Rgb8Pixel::default(); size.width as usize * size.height as usize];
+        self.window.request_redraw();
+        self.window.draw_if_needed(|renderer| {
+            renderer.render(&mut buffer, size.width as usize);
+        });
+
+        RgbImage::from_raw(size.width, size.height, buffer.as_bytes().to_vec()).unwrap()
+    }
+
+    /// Renders the window and compares it with the golden image at `path`.
+    ///
+    /// If `VINYL_UPDATE_GOLDEN` is set, it is written instead. On a mismatch, or if the golden
+    /// image is missing, the actual render is saved next to it for inspection.
+    pub fn assert_golden(&self, path: &Path) -> Result<()> {
+        let actual = self.render();
+
+        if std::env::var_os("VINYL_UPDATE_GOLDEN").is_some() {
+            actual.save(path)?;
+            return Ok(());
+        }
+
+        let actual_path = path.with_extension("actual.png");
+        if !path.exists() {
+            actual.save(&actual_path)?;
+            return Err(anyhow!(
+                "Missing golden image {}, set VINYL_UPDATE_GOLDEN=1 to write it",
+                path.display()
+            ));
+        }
+
+        let expected = image::open(path)?.to_rgb8();
+        if expected != actual {
+            actual.save(&actual_path)?;
+            return Err(anyhow!(
+                "{} does not match golden image {}",
+                actual_path.display(),
+                path.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use slint::{ComponentHandle, ModelRc, VecModel};
+
+    use super::Headless;
+    use crate::components;
+    use crate::input::Key;
+    use crate::{LibraryModel, MainWindow, NowPlaying, Song};
+
+    fn golden(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{name}.png"))
+    }
+
+    fn song(title: &str) -> Song {
+        Song {
+            path: format!("/music/{title}.flac").into(),
+            title: title.into(),
+            artist: "Nine Inch Nails".into(),
+            album: "The Downward Spiral".into(),
+            cover_art: Default::default(),
+            duration: 4 * 60 + 33,
+            chapters: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_player() {
+        let headless = Headless::init(640, 480);
+        let app = MainWindow::new().unwrap();
+        components::init_format(&app);
+        app.show().unwrap();
+
+        let now_playing = app.global::<NowPlaying>();
+        now_playing.set_song(song("Hurt"));
+        now_playing.set_progress(95);
+        now_playing.set_is_playing(true);
+
+        headless.assert_golden(&golden("player")).unwrap();
+    }
+
+    #[test]
+    fn test_library() {
+        let headless = Headless::init(640, 480);
+        let app = MainWindow::new().unwrap();
+        components::init_format(&app);
+        app.show().unwrap();
+
+        app.global::<LibraryModel>()
+            .set_songs(ModelRc::new(VecModel::from(vec![
+                song("Mr. Self Destruct"),
+                song("Piggy"),
+                song("Heresy"),
+            ])));
+
+        headless.assert_golden(&golden("library")).unwrap();
+    }
+
+    #[test]
+    fn test_settings() {
+        let headless = Headless::init(640, 480);
+        let app = MainWindow::new().unwrap();
+        components::init_format(&app);
+        app.show().unwrap();
+
+        app.global::<NowPlaying>().set_song(song("Hurt"));
+        headless.press(Key::Start);
+
+        headless.assert_golden(&golden("settings")).unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 use slint::SharedString;
 use strum::{AsRefStr, Display};
 
-#[derive(Debug, Copy, Clone, Display, AsRefStr, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, Display, AsRefStr, PartialEq, Eq, Hash)]
 #[strum(serialize_all = "kebab-case")]
 pub enum Key {
     Up,
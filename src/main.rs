@@ -1,26 +1,47 @@
-#![feature(lazy_cell)]
+#![cfg_attr(test, feature(test))]
 
 mod audio;
+mod battery;
+mod chapters;
 mod components;
+mod config;
 mod image;
 mod input;
 mod song;
+mod state;
 
+#[cfg(all(test, feature = "headless"))]
+mod headless;
 #[cfg(feature = "miyoo")]
 mod miyoo;
 
+#[cfg(all(feature = "simulator", feature = "headless"))]
+compile_error!(
+    "features `simulator` and `headless` are mutually exclusive, use `--no-default-features`"
+);
+
 use std::{
+    cell::{Cell, RefCell},
     path::{Path, PathBuf},
+    rc::Rc,
+    thread,
     time::Duration,
 };
 
 use anyhow::Result;
 use clap::Parser;
-use log::{info, LevelFilter};
+use log::{info, warn, LevelFilter};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
 use simple_logger::SimpleLogger;
 use slint::Timer;
 
+use crate::audio::{Audio, AudioArgs};
+use crate::config::Config;
 use crate::song::SongData;
+use crate::state::State;
 
 slint::include_modules!();
 
@@ -29,8 +50,14 @@ slint::include_modules!();
 #[command(bin_name = "vinyl")]
 struct VinylCli {
     path: Option<PathBuf>,
+    #[arg(short, long, default_value = "config.toml")]
+    config: PathBuf,
+    #[arg(short, long, default_value = "state.toml")]
+    state: PathBuf,
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
+    #[command(flatten)]
+    audio: AudioArgs,
 }
 
 fn main() -> Result<()> {
@@ -54,23 +81,53 @@ fn main() -> Result<()> {
         .init()
         .unwrap();
 
-    run(args.path.as_deref())?;
+    let config = Config::load(&args.config)?;
+    let state = Rc::new(RefCell::new(State::load(&args.state)?));
+    let audio = Rc::from(audio::open(&args.audio, &config.audio)?);
+
+    run(&config, state, audio, args.path.as_deref())?;
 
     Ok(())
 }
 
-fn run(path: Option<&Path>) -> Result<()> {
+fn run(
+    config: &Config,
+    state: Rc<RefCell<State>>,
+    audio: Rc<dyn Audio>,
+    path: Option<&Path>,
+) -> Result<()> {
     #[cfg(feature = "miyoo")]
-    {
-        slint::platform::set_platform(Box::new(miyoo::MyPlatform::new())).unwrap();
-    }
+    let screen = {
+        let platform = miyoo::MyPlatform::new(config, state.clone())?;
+        let screen = platform.screen();
+        slint::platform::set_platform(Box::new(platform)).unwrap();
+        screen
+    };
 
     info!("initializing Vinyl...");
     let app = MainWindow::new().unwrap();
 
+    #[cfg(feature = "miyoo")]
+    let (lock_screen, set_brightness): (Option<Box<dyn Fn()>>, Option<Box<dyn Fn(u8)>>) = {
+        screen.on_brightness_changed({
+            let app = app.as_weak();
+            move || app.unwrap().global::<SettingsModel>().invoke_refresh()
+        });
+        let lock = screen.clone();
+        (
+            Some(Box::new(move || lock.lock())),
+            Some(Box::new(move |percent| screen.set_brightness(percent))),
+        )
+    };
+    #[cfg(not(feature = "miyoo"))]
+    let (lock_screen, set_brightness) = (None, None);
+
     let timer = Timer::default();
     timer.start(slint::TimerMode::Repeated, Duration::from_secs(1), {
         let app = app.as_weak();
+        // Song time played that has not made a whole second yet, as it runs at the playback
+        // speed.
+        let fraction = Cell::new(0.0);
         move || {
             let app = app.unwrap();
             let now_playing = app.global::<NowPlaying>();
@@ -80,17 +137,14 @@ fn run(path: Option<&Path>) -> Result<()> {
                 if progress >= song.duration {
                     info!("end song");
                 } else {
-                    now_playing.set_progress(progress + 1);
+                    let elapsed = fraction.get() + now_playing.get_speed();
+                    fraction.set(elapsed.fract());
+                    now_playing.set_progress((progress + elapsed as i32).min(song.duration));
                 }
             }
         }
     });
 
-    if let Some(path) = path {
-        app.global::<NowPlaying>()
-            .set_song((&SongData::load(path.to_path_buf()).unwrap()).into());
-    }
-
     // app.global::<LibraryModel>().set_songs(
     //     [
     //         "/mnt/d/Music/Nine Inch Nails/The Downward Spiral/1-01 Mr. Self Destruct.m4a",
@@ -115,16 +169,44 @@ fn run(path: Option<&Path>) -> Result<()> {
     //     .into(),
     // );
 
-    app.global::<Format>().on_format_time(|seconds: i32| {
-        let minutes = seconds / 60;
-        let seconds = seconds % 60;
-        format!("{minutes:02}:{seconds:02}").into()
-    });
+    let _timers = components::init(
+        &app,
+        config,
+        state.clone(),
+        audio.clone(),
+        lock_screen,
+        set_brightness,
+    );
 
-    components::init(&app);
+    if let Some(path) = path {
+        app.global::<NowPlaying>()
+            .invoke_load_song((&SongData::load(path.to_path_buf()).unwrap()).into());
+    } else {
+        components::now_playing::restore_state(&app, &state);
+    }
+
+    handle_signals()?;
 
     info!("running event loop");
     app.run().unwrap();
 
+    info!("shutting down");
+    components::now_playing::save_state(&app, &state);
+    if let Err(e) = audio.stop() {
+        warn!("Failed to stop audio: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Quits the event loop on SIGTERM/SIGINT, so frontends can close us cleanly.
+fn handle_signals() -> Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("received signal {}, quitting", signal);
+            let _ = slint::quit_event_loop();
+        }
+    });
     Ok(())
 }
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+/// The display backlight, driven through sysfs.
+pub struct Backlight {
+    brightness: PathBuf,
+    max: u32,
+}
+
+impl Backlight {
+    pub fn open() -> Result<Self> {
+        // Prefer a regular backlight class device if the kernel exposes one.
+        if let Some(dir) = fs::read_dir("/sys/class/backlight")
+            .ok()
+            .and_then(|mut entries| entries.find_map(|entry| entry.ok()))
+            .map(|entry| entry.path())
+        {
+            return Ok(Self {
+                brightness: dir.join("brightness"),
+                max: read_u32(&dir.join("max_brightness"))?,
+            });
+        }
+
+        // The Miyoo Mini drives its backlight with a PWM, where the duty cycle is bounded by
+        // the period.
+        let pwm = Path::new("/sys/class/pwm/pwmchip0/pwm0");
+        if pwm.exists() {
+            return Ok(Self {
+                brightness: pwm.join("duty_cycle"),
+                max: read_u32(&pwm.join("period")).unwrap_or(100),
+            });
+        }
+
+        Err(anyhow!("No backlight found"))
+    }
+
+    /// Sets the brightness, in percent.
+    pub fn set(&self, percent: u8) -> Result<()> {
+        let value = self.max * percent.min(100) as u32 / 100;
+        fs::write(&self.brightness, value.to_string())
+            .with_context(|| format!("Failed to write {}", self.brightness.display()))
+    }
+}
+
+fn read_u32(path: &Path) -> Result<u32> {
+    fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .parse()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
@@ -0,0 +1,190 @@
+use std::ops::Range;
+use std::os::fd::AsRawFd;
+
+use anyhow::Result;
+use framebuffer::{Framebuffer, VarScreeninfo};
+use log::{debug, warn};
+use nix::{ioctl_write_int_bad, ioctl_write_ptr_bad};
+use slint::platform::software_renderer::PhysicalRegion;
+
+use crate::miyoo::pixel::{Frame, PixelFormat};
+
+ioctl_write_ptr_bad!(fbiopan_display, 0x4606, VarScreeninfo);
+ioctl_write_int_bad!(fbioblank, 0x4611);
+
+const FB_BLANK_UNBLANK: i32 = 0;
+const FB_BLANK_POWERDOWN: i32 = 4;
+
+/// The framebuffer device we present rendered frames to.
+///
+/// If the virtual resolution has room for two pages, frames are drawn to the hidden page and
+/// then panned to, so the screen never shows a half-copied frame. Otherwise only the lines that
+/// changed are copied straight to the visible page.
+pub struct Display {
+    framebuffer: Framebuffer,
+    pub format: PixelFormat,
+    pub width: usize,
+    pub height: usize,
+    line_length: usize,
+    /// Byte offset of the first visible pixel in a page.
+    x_offset: usize,
+    /// The page that is not on screen, if page flipping is available.
+    back_page: Option<usize>,
+    /// Lines that changed in the previous frame, which the back page has not seen yet.
+    previous_dirty: Range<usize>,
+    /// How many of the next frames must be copied in full, because the pages were cleared.
+    full_copies: usize,
+}
+
+impl Display {
+    /// Fails if the framebuffer's pixel format is not one we can draw.
+    pub fn new(framebuffer: Framebuffer) -> Result<Self> {
+        debug!(
+            "init fb: var_screen_info: {:?}, fix_screen_info: {:?}",
+            framebuffer.var_screen_info, framebuffer.fix_screen_info,
+        );
+
+        let var_screen_info = &framebuffer.var_screen_info;
+        let (width, height) = (
+            var_screen_info.xres as usize,
+            var_screen_info.yres as usize,
+        );
+        let format = PixelFormat::detect(
+            var_screen_info.bits_per_pixel,
+            var_screen_info.red.offset,
+            var_screen_info.green.offset,
+            var_screen_info.blue.offset,
+        )?;
+        let bytes_per_pixel = var_screen_info.bits_per_pixel as usize / 8;
+        let line_length = framebuffer.fix_screen_info.line_length as usize;
+        let x_offset = var_screen_info.xoffset as usize * bytes_per_pixel;
+
+        let page_flipping = var_screen_info.yres_virtual as usize >= height * 2
+            && framebuffer.frame.len() >= line_length * height * 2;
+        let back_page = if page_flipping {
+            Some(if var_screen_info.yoffset as usize >= height {
+                0
+            } else {
+                1
+            })
+        } else {
+            None
+        };
+        debug!(
+            "pixel format: {:?}, page flipping: {}",
+            format, page_flipping
+        );
+
+        Ok(Self {
+            framebuffer,
+            format,
+            width,
+            height,
+            line_length,
+            x_offset,
+            back_page,
+            previous_dirty: 0..0,
+            full_copies: if page_flipping { 2 } else { 1 },
+        })
+    }
+
+    /// Shows a rendered frame. `dirty` is the region the renderer reported as changed.
+    pub fn present(&mut self, frame: &Frame, dirty: &PhysicalRegion) {
+        let dirty = self.dirty_lines(dirty);
+        let lines = if self.full_copies > 0 {
+            self.full_copies -= 1;
+            0..self.height
+        } else if self.back_page.is_some() {
+            // The back page is two frames behind, so it is missing both frames' changes.
+            union(&dirty, &self.previous_dirty)
+        } else {
+            dirty.clone()
+        };
+        self.previous_dirty = dirty;
+
+        match self.back_page {
+            Some(page) => {
+                self.copy_lines(frame, lines, page);
+                if self.pan(page) {
+                    self.back_page = Some(1 - page);
+                } else {
+                    warn!("Failed to pan display, falling back to single buffering");
+                    self.back_page = None;
+                    self.full_copies = 1;
+                }
+            }
+            None => {
+                let page = self.framebuffer.var_screen_info.yoffset as usize / self.height;
+                self.copy_lines(frame, lines, page);
+            }
+        }
+    }
+
+    /// Turns the display off or back on. Falls back to clearing the screen if the driver does not
+    /// support blanking.
+    pub fn blank(&mut self, blank: bool) {
+        let mode = if blank {
+            FB_BLANK_POWERDOWN
+        } else {
+            FB_BLANK_UNBLANK
+        };
+        if let Err(e) = unsafe { fbioblank(self.framebuffer.device.as_raw_fd(), mode) } {
+            warn!("Failed to blank framebuffer: {}", e);
+            if blank {
+                self.clear();
+            }
+        }
+    }
+
+    /// Fills every page with black, and goes back to the first page.
+    pub fn clear(&mut self) {
+        self.framebuffer.frame.fill(0);
+        if self.back_page.is_some() {
+            self.pan(0);
+            self.back_page = Some(1);
+            self.full_copies = 2;
+        } else {
+            self.full_copies = 1;
+        }
+    }
+
+    fn copy_lines(&mut self, frame: &Frame, lines: Range<usize>, page: usize) {
+        let frame = frame.as_bytes();
+        let frame_line_length = frame.len() / self.height;
+        let page_offset = page * self.height * self.line_length + self.x_offset;
+        for y in lines {
+            let offset = page_offset + y * self.line_length;
+            self.framebuffer.frame[offset..offset + frame_line_length]
+                .copy_from_slice(&frame[y * frame_line_length..(y + 1) * frame_line_length]);
+        }
+    }
+
+    fn pan(&mut self, page: usize) -> bool {
+        let mut var_screen_info = self.framebuffer.var_screen_info.clone();
+        var_screen_info.yoffset = (page * self.height) as u32;
+        match unsafe { fbiopan_display(self.framebuffer.device.as_raw_fd(), &var_screen_info) } {
+            Ok(_) => {
+                self.framebuffer.var_screen_info = var_screen_info;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The range of buffer lines covered by a dirty region.
+    fn dirty_lines(&self, region: &PhysicalRegion) -> Range<usize> {
+        let start = region.bounding_box_origin().y.max(0) as usize;
+        let end = start + region.bounding_box_size().height as usize;
+        start.min(self.height)..end.min(self.height)
+    }
+}
+
+fn union(a: &Range<usize>, b: &Range<usize>) -> Range<usize> {
+    if a.is_empty() {
+        b.clone()
+    } else if b.is_empty() {
+        a.clone()
+    } else {
+        a.start.min(b.start)..a.end.max(b.end)
+    }
+}
@@ -9,6 +9,19 @@ pub struct Evdev {
     pub device: Device,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyState {
+    Released,
+    Pressed,
+    Repeated,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub state: KeyState,
+}
+
 impl Evdev {
     pub fn new() -> Self {
         Self {
@@ -16,27 +29,45 @@ impl Evdev {
         }
     }
 
-    pub fn fetch_events(&mut self) -> Option<WindowEvent> {
-        for event in self.device.fetch_events().unwrap() {
-            match event.event_type() {
-                EventType::KEY => {
-                    if event.timestamp().elapsed().unwrap() > MAXIMUM_FRAME_TIME {
-                        continue;
-                    }
-                    let key = Key::from(evdev::Key(event.code()));
-                    let text = key.into();
-                    if let Some(text) = text {
-                        return Some(match event.value() {
-                            0 => WindowEvent::KeyReleased { text },
-                            1 => WindowEvent::KeyPressed { text },
-                            2 => WindowEvent::KeyPressRepeated { text },
-                            _ => unreachable!(),
-                        });
-                    }
+    /// Blocks until the next batch of input events arrives, and returns every key event in it.
+    /// Presses and repeats that are too old to act on are dropped, but releases never are, so
+    /// that no key is left held down.
+    pub fn fetch_events(&mut self) -> std::io::Result<Vec<KeyEvent>> {
+        Ok(self
+            .device
+            .fetch_events()?
+            .filter(|event| event.event_type() == EventType::KEY)
+            .filter_map(|event| {
+                let key = Key::from(evdev::Key(event.code()));
+                if key == Key::Unknown {
+                    return None;
                 }
-                _ => {}
-            }
-        }
-        return None;
+                let state = match event.value() {
+                    0 => KeyState::Released,
+                    1 => KeyState::Pressed,
+                    2 => KeyState::Repeated,
+                    _ => return None,
+                };
+                let stale = event
+                    .timestamp()
+                    .elapsed()
+                    .is_ok_and(|elapsed| elapsed > MAXIMUM_FRAME_TIME);
+                if stale && state != KeyState::Released {
+                    return None;
+                }
+                Some(KeyEvent { key, state })
+            })
+            .collect())
+    }
+}
+
+impl From<KeyEvent> for Option<WindowEvent> {
+    fn from(event: KeyEvent) -> Option<WindowEvent> {
+        let text = Option::<slint::SharedString>::from(event.key)?;
+        Some(match event.state {
+            KeyState::Released => WindowEvent::KeyReleased { text },
+            KeyState::Pressed => WindowEvent::KeyPressed { text },
+            KeyState::Repeated => WindowEvent::KeyPressRepeated { text },
+        })
     }
 }
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::input::Key;
+use crate::miyoo::evdev::{KeyEvent, KeyState};
+
+/// Releasing power sooner than this after pressing it toggles the lock.
+const POWER_TAP_TIME: Duration = Duration::from_millis(1000);
+
+/// Holding power for this long triggers the configured power action.
+const POWER_HOLD_TIME: Duration = Duration::from_millis(2000);
+
+/// Holding all of these keys toggles the lock.
+const LOCK_COMBO: [Key; 2] = [Key::Menu, Key::Select];
+
+/// What the event loop should do with a key event after it went through the lock.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockEvent {
+    Forward,
+    Swallow,
+    /// A held back key was released on its own; the UI should see it pressed and released.
+    Tap,
+    Lock,
+    Unlock,
+}
+
+/// Tracks whether the screen is locked. While locked, only the volume keys reach the UI.
+pub struct ScreenLock {
+    locked: bool,
+    auto_lock: Option<Duration>,
+    last_input: Instant,
+    held: HashSet<Key>,
+    /// Keys that toggled the lock; their repeats and releases must not reach the UI.
+    swallowed: HashSet<Key>,
+    /// A lock combo key whose press is held back until it is clear that it is not part of the
+    /// combo, so the UI never sees a press without its release.
+    pending: Option<Key>,
+    power_pressed_at: Option<Instant>,
+}
+
+impl ScreenLock {
+    pub fn new(auto_lock: Option<Duration>) -> Self {
+        Self {
+            locked: false,
+            auto_lock,
+            last_input: Instant::now(),
+            held: HashSet::new(),
+            swallowed: HashSet::new(),
+            pending: None,
+            power_pressed_at: None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn lock(&mut self) {
+        debug!("lock screen");
+        self.locked = true;
+    }
+
+    pub fn is_held(&self, key: Key) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// Keeps the UI from seeing `key` until it is released, as it was used in a combo.
+    pub fn swallow(&mut self, key: Key) {
+        if self.held.contains(&key) {
+            if self.pending == Some(key) {
+                self.pending = None;
+            }
+            self.swallowed.insert(key);
+        }
+    }
+
+    /// The held back press of a combo key, which must reach the UI before the event that was
+    /// just forwarded.
+    pub fn take_pending(&mut self) -> Option<KeyEvent> {
+        self.pending.take().map(|key| KeyEvent {
+            key,
+            state: KeyState::Pressed,
+        })
+    }
+
+    /// Time since the last key event.
+    pub fn idle_time(&self) -> Duration {
+        self.last_input.elapsed()
+    }
+
+    /// How long until the screen should lock itself, if it is unlocked and auto-lock is enabled.
+    pub fn time_until_auto_lock(&self) -> Option<Duration> {
+        if self.locked {
+            return None;
+        }
+        self.auto_lock
+            .map(|timeout| timeout.saturating_sub(self.last_input.elapsed()))
+    }
+
+    /// How long until the held power button counts as a long press, if it is held.
+    pub fn time_until_power_hold(&self) -> Option<Duration> {
+        self.power_pressed_at
+            .map(|pressed_at| POWER_HOLD_TIME.saturating_sub(pressed_at.elapsed()))
+    }
+
+    /// Consumes a long press of the power button, so its release is not treated as a tap.
+    pub fn take_power_hold(&mut self) -> bool {
+        if self.time_until_power_hold() == Some(Duration::ZERO) {
+            self.power_pressed_at = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Resets the idle timer, e.g. after waking up from suspend.
+    pub fn touch(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    pub fn handle(&mut self, event: KeyEvent) -> LockEvent {
+        self.last_input = Instant::now();
+
+        let key = event.key;
+        match event.state {
+            KeyState::Pressed => {
+                self.held.insert(key);
+            }
+            KeyState::Released => {
+                self.held.remove(&key);
+            }
+            KeyState::Repeated => {}
+        }
+
+        if self.swallowed.contains(&key) {
+            if event.state == KeyState::Released {
+                self.swallowed.remove(&key);
+            }
+            return LockEvent::Swallow;
+        }
+
+        if key == Key::Power {
+            match event.state {
+                KeyState::Pressed => self.power_pressed_at = Some(Instant::now()),
+                KeyState::Released => {
+                    let pressed_at = self.power_pressed_at.take();
+                    if pressed_at.is_some_and(|t| t.elapsed() < POWER_TAP_TIME) {
+                        return self.toggle();
+                    }
+                }
+                KeyState::Repeated => {}
+            }
+            return LockEvent::Swallow;
+        }
+
+        if event.state == KeyState::Pressed && LOCK_COMBO.iter().all(|k| self.held.contains(k)) {
+            self.pending = None;
+            self.swallowed.extend(LOCK_COMBO);
+            return self.toggle();
+        }
+
+        if self.locked && !matches!(key, Key::VolumeUp | Key::VolumeDown) {
+            return LockEvent::Swallow;
+        }
+
+        if self.pending == Some(key) {
+            return match event.state {
+                KeyState::Released => {
+                    self.pending = None;
+                    LockEvent::Tap
+                }
+                _ => LockEvent::Swallow,
+            };
+        }
+        if event.state == KeyState::Pressed && self.pending.is_none() && LOCK_COMBO.contains(&key) {
+            self.pending = Some(key);
+            return LockEvent::Swallow;
+        }
+
+        LockEvent::Forward
+    }
+
+    fn toggle(&mut self) -> LockEvent {
+        if self.locked {
+            debug!("unlock screen");
+            self.locked = false;
+            LockEvent::Unlock
+        } else {
+            self.lock();
+            LockEvent::Lock
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LockEvent, ScreenLock};
+    use crate::input::Key;
+    use crate::miyoo::evdev::{KeyEvent, KeyState};
+
+    fn event(key: Key, state: KeyState) -> KeyEvent {
+        KeyEvent { key, state }
+    }
+
+    #[test]
+    fn test_combo() {
+        let mut lock = ScreenLock::new(None);
+
+        // A combo key on its own is held back until it is released.
+        assert_eq!(
+            lock.handle(event(Key::Menu, KeyState::Pressed)),
+            LockEvent::Swallow
+        );
+        assert_eq!(
+            lock.handle(event(Key::Menu, KeyState::Released)),
+            LockEvent::Tap
+        );
+
+        // Nothing of the combo reaches the UI.
+        lock.handle(event(Key::Menu, KeyState::Pressed));
+        assert_eq!(
+            lock.handle(event(Key::Select, KeyState::Pressed)),
+            LockEvent::Lock
+        );
+        assert_eq!(lock.take_pending(), None);
+        assert_eq!(
+            lock.handle(event(Key::Menu, KeyState::Released)),
+            LockEvent::Swallow
+        );
+        assert_eq!(
+            lock.handle(event(Key::Select, KeyState::Released)),
+            LockEvent::Swallow
+        );
+        assert!(lock.is_locked());
+
+        // The held back press goes first when another key is pressed with it.
+        lock.handle(event(Key::Menu, KeyState::Pressed));
+        lock.handle(event(Key::Select, KeyState::Pressed));
+        lock.handle(event(Key::Menu, KeyState::Released));
+        lock.handle(event(Key::Select, KeyState::Released));
+        lock.handle(event(Key::Select, KeyState::Pressed));
+        assert_eq!(
+            lock.handle(event(Key::A, KeyState::Pressed)),
+            LockEvent::Forward
+        );
+        assert_eq!(
+            lock.take_pending(),
+            Some(event(Key::Select, KeyState::Pressed))
+        );
+        assert_eq!(
+            lock.handle(event(Key::Select, KeyState::Released)),
+            LockEvent::Forward
+        );
+    }
+}
@@ -1,4 +1,8 @@
+mod brightness;
+mod display;
 mod evdev;
+mod lock;
+mod pixel;
 mod platform;
 
 pub use platform::MyPlatform;
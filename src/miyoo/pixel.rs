@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use slint::platform::software_renderer::{
+    PhysicalRegion, PremultipliedRgbaColor, Rgb565Pixel, SoftwareRenderer, TargetPixel,
+};
+
+/// Layouts of framebuffer pixels that we know how to render into.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    Bgrx8888,
+    Rgbx8888,
+    Bgr888,
+    Rgb888,
+    Rgb565,
+}
+
+impl PixelFormat {
+    /// Picks the pixel format from the `var_screen_info` of the framebuffer. Channel offsets are
+    /// in bits, counted from the least significant bit of the little-endian pixel.
+    pub fn detect(bits_per_pixel: u32, red: u32, green: u32, blue: u32) -> Result<Self> {
+        match (bits_per_pixel, red, green, blue) {
+            (32, 16, 8, 0) => Ok(Self::Bgrx8888),
+            (32, 0, 8, 16) => Ok(Self::Rgbx8888),
+            (24, 16, 8, 0) => Ok(Self::Bgr888),
+            (24, 0, 8, 16) => Ok(Self::Rgb888),
+            (16, 11, 5, 0) => Ok(Self::Rgb565),
+            _ => Err(anyhow!(
+                "Unsupported pixel format: {bits_per_pixel} bpp, red at {red}, green at {green}, blue at {blue}"
+            )),
+        }
+    }
+}
+
+/// An in-memory frame in the same layout as the framebuffer, so it can be copied over as is.
+pub enum Frame {
+    Bgrx8888(Vec<Pixel<4, 2, 1, 0>>),
+    Rgbx8888(Vec<Pixel<4, 0, 1, 2>>),
+    Bgr888(Vec<Pixel<3, 2, 1, 0>>),
+    Rgb888(Vec<Pixel<3, 0, 1, 2>>),
+    Rgb565(Vec<Rgb565Pixel>),
+}
+
+impl Frame {
+    pub fn new(format: PixelFormat, len: usize) -> Self {
+        match format {
+            PixelFormat::Bgrx8888 => Self::Bgrx8888(vec![Pixel::default(); len]),
+            PixelFormat::Rgbx8888 => Self::Rgbx8888(vec![Pixel::default(); len]),
+            PixelFormat::Bgr888 => Self::Bgr888(vec![Pixel::default(); len]),
+            PixelFormat::Rgb888 => Self::Rgb888(vec![Pixel::default(); len]),
+            PixelFormat::Rgb565 => Self::Rgb565(vec![Rgb565Pixel::default(); len]),
+        }
+    }
+
+    pub fn render(&mut self, renderer: &SoftwareRenderer, pixel_stride: usize) -> PhysicalRegion {
+        match self {
+            Self::Bgrx8888(frame) => renderer.render(frame, pixel_stride),
+            Self::Rgbx8888(frame) => renderer.render(frame, pixel_stride),
+            Self::Bgr888(frame) => renderer.render(frame, pixel_stride),
+            Self::Rgb888(frame) => renderer.render(frame, pixel_stride),
+            Self::Rgb565(frame) => renderer.render(frame, pixel_stride),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Bgrx8888(frame) => frame.as_bytes(),
+            Self::Rgbx8888(frame) => frame.as_bytes(),
+            Self::Bgr888(frame) => frame.as_bytes(),
+            Self::Rgb888(frame) => frame.as_bytes(),
+            Self::Rgb565(frame) => frame.as_bytes(),
+        }
+    }
+}
+
+/// A pixel of `N` bytes, with the red, green and blue channels at byte `R`, `G` and `B`.
+/// Any other byte is padding.
+#[derive(Debug, Copy, Clone)]
+#[repr(transparent)]
+pub struct Pixel<const N: usize, const R: usize, const G: usize, const B: usize>([u8; N]);
+
+impl<const N: usize, const R: usize, const G: usize, const B: usize> Default for Pixel<N, R, G, B> {
+    fn default() -> Self {
+        Self([0; N])
+    }
+}
+
+impl<const N: usize, const R: usize, const G: usize, const B: usize> TargetPixel
+    for Pixel<N, R, G, B>
+{
+    fn blend(&mut self, color: PremultipliedRgbaColor) {
+        let a = (u8::MAX - color.alpha) as u16;
+        self.0[B] = (self.0[B] as u16 * a / 255) as u8 + color.blue;
+        self.0[G] = (self.0[G] as u16 * a / 255) as u8 + color.green;
+        self.0[R] = (self.0[R] as u16 * a / 255) as u8 + color.red;
+    }
+
+    fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        let mut pixel = [255; N];
+        pixel[R] = r;
+        pixel[G] = g;
+        pixel[B] = b;
+        Self(pixel)
+    }
+}
+
+trait AsBytes {
+    fn as_bytes(&self) -> &[u8];
+}
+
+impl<T: TargetPixel> AsBytes for [T] {
+    /// The pixels interpreted as raw bytes, in machine's native endian.
+    #[inline]
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self.as_ptr() as *const _,
+                self.len() * core::mem::size_of::<T>(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PixelFormat;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            PixelFormat::detect(32, 16, 8, 0).unwrap(),
+            PixelFormat::Bgrx8888
+        );
+        assert_eq!(
+            PixelFormat::detect(24, 0, 8, 16).unwrap(),
+            PixelFormat::Rgb888
+        );
+        assert_eq!(PixelFormat::detect(16, 11, 5, 0).unwrap(), PixelFormat::Rgb565);
+        assert!(PixelFormat::detect(8, 0, 0, 0).is_err());
+    }
+}
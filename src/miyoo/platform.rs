@@ -1,65 +1,178 @@
 use std::cell::Cell;
-use std::sync::mpsc::channel;
+use std::fs;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::thread;
+use std::time::Duration;
 use std::{cell::RefCell, rc::Rc};
 
+use anyhow::{Context, Result};
 use framebuffer::Framebuffer;
-use log::debug;
-use slint::platform::software_renderer::{PremultipliedRgbaColor, TargetPixel};
+use log::{debug, error, info, warn};
 use slint::{
     platform::{
         software_renderer::{MinimalSoftwareWindow, RenderingRotation, RepaintBufferType},
-        Platform, WindowAdapter,
+        EventLoopProxy, Platform, WindowAdapter, WindowEvent,
     },
-    PhysicalSize,
+    EventLoopError, PhysicalSize,
 };
 
-use crate::miyoo::evdev::Evdev;
+use crate::config::{Config, PowerAction};
+use crate::input::Key;
+use crate::miyoo::brightness::Backlight;
+use crate::miyoo::evdev::{Evdev, KeyEvent, KeyState};
+use crate::miyoo::display::Display;
+use crate::miyoo::lock::{LockEvent, ScreenLock};
+use crate::miyoo::pixel::Frame;
+use crate::state::{State, BRIGHTNESS_STEP, MIN_BRIGHTNESS};
+
+/// How often to wake up and redraw while an animation is running.
+const ANIMATION_FRAME_TIME: Duration = Duration::from_millis(1000 / 30);
+
+enum Event {
+    Input(KeyEvent),
+    Invoke(Box<dyn FnOnce() + Send>),
+    Lock,
+    Brightness(u8),
+    Quit,
+}
+
+struct Proxy {
+    sender: Sender<Event>,
+}
+
+impl EventLoopProxy for Proxy {
+    fn quit_event_loop(&self) -> Result<(), EventLoopError> {
+        self.sender
+            .send(Event::Quit)
+            .map_err(|_| EventLoopError::EventLoopTerminated)
+    }
+
+    fn invoke_from_event_loop(
+        &self,
+        event: Box<dyn FnOnce() + Send>,
+    ) -> Result<(), EventLoopError> {
+        self.sender
+            .send(Event::Invoke(event))
+            .map_err(|_| EventLoopError::EventLoopTerminated)
+    }
+}
+
+/// Lets the rest of the app lock the screen, e.g. when the sleep timer runs out, and share the
+/// backlight with the platform.
+#[derive(Clone)]
+pub struct Screen {
+    sender: Sender<Event>,
+    brightness_changed: Rc<RefCell<Option<Box<dyn Fn()>>>>,
+}
+
+impl Screen {
+    pub fn lock(&self) {
+        let _ = self.sender.send(Event::Lock);
+    }
+
+    /// Sets the backlight to the user's brightness, in percent.
+    pub fn set_brightness(&self, percent: u8) {
+        let _ = self.sender.send(Event::Brightness(percent));
+    }
+
+    /// Calls `f` after the brightness was changed with Menu + Up/Down.
+    pub fn on_brightness_changed(&self, f: impl Fn() + 'static) {
+        *self.brightness_changed.borrow_mut() = Some(Box::new(f));
+    }
+}
 
 pub struct MyPlatform {
     evdev: Cell<Option<Evdev>>,
-    framebuffer: RefCell<Framebuffer>,
-    buffer_size: usize,
-    buffer_offset: usize,
+    sender: Sender<Event>,
+    receiver: Receiver<Event>,
+    display: RefCell<Display>,
     window: Rc<MinimalSoftwareWindow>,
+    /// The only handle to the backlight, which the rest of the app changes through [`Screen`].
+    backlight: Option<Backlight>,
+    brightness_changed: Rc<RefCell<Option<Box<dyn Fn()>>>>,
+    state: Rc<RefCell<State>>,
+    dim_after: Option<Duration>,
+    dim_brightness: u8,
+    auto_lock: Option<Duration>,
+    power_action: PowerAction,
 }
 
 impl MyPlatform {
-    pub fn new() -> Self {
-        let framebuffer = Framebuffer::new("/dev/fb0").expect("Failed to open /dev/fb0");
-        debug!(
-            "init fb: var_screen_info: {:?}, fix_screen_info: {:?}",
-            framebuffer.var_screen_info, framebuffer.fix_screen_info,
-        );
-
-        let (width, height, xoffset, yoffset) = (
-            framebuffer.var_screen_info.xres as usize,
-            framebuffer.var_screen_info.yres as usize,
-            framebuffer.var_screen_info.xoffset as usize,
-            framebuffer.var_screen_info.yoffset as usize,
-        );
-        let bytes_per_pixel = framebuffer.var_screen_info.bits_per_pixel / 8;
-        let buffer_size = width * height * bytes_per_pixel as usize;
-        let buffer_offset = (yoffset * width + xoffset) * bytes_per_pixel as usize;
+    pub fn new(config: &Config, state: Rc<RefCell<State>>) -> Result<Self> {
+        let framebuffer = Framebuffer::new("/dev/fb0").context("Failed to open /dev/fb0")?;
+        let display = Display::new(framebuffer)?;
+        let (width, height) = (display.width, display.height);
+
+        let rotation = match config.screen.rotation {
+            0 => RenderingRotation::NoRotation,
+            90 => RenderingRotation::Rotate90,
+            180 => RenderingRotation::Rotate180,
+            270 => RenderingRotation::Rotate270,
+            rotation => {
+                warn!("Unsupported rotation {}, ignoring", rotation);
+                RenderingRotation::NoRotation
+            }
+        };
 
         let window = MinimalSoftwareWindow::new(RepaintBufferType::ReusedBuffer);
         window.request_redraw();
-        window.draw_if_needed(|renderer| {
-            renderer.set_rendering_rotation(RenderingRotation::Rotate180)
-        });
-        window.set_size(PhysicalSize::new(
-            framebuffer.var_screen_info.xres,
-            framebuffer.var_screen_info.yres,
-        ));
-        let framebuffer = RefCell::new(framebuffer);
+        window.draw_if_needed(|renderer| renderer.set_rendering_rotation(rotation));
+        if matches!(
+            rotation,
+            RenderingRotation::Rotate90 | RenderingRotation::Rotate270
+        ) {
+            window.set_size(PhysicalSize::new(height as u32, width as u32));
+        } else {
+            window.set_size(PhysicalSize::new(width as u32, height as u32));
+        }
+        let display = RefCell::new(display);
         let evdev = Cell::new(Some(Evdev::new()));
+        let (sender, receiver) = channel();
 
-        Self {
+        Ok(Self {
             evdev,
-            framebuffer,
-            buffer_size,
-            buffer_offset,
+            sender,
+            receiver,
+            display,
             window,
+            backlight: Backlight::open()
+                .map_err(|e| warn!("Failed to open backlight: {}", e))
+                .ok(),
+            brightness_changed: Default::default(),
+            state,
+            dim_after: (config.screen.dim_secs > 0)
+                .then(|| Duration::from_secs(config.screen.dim_secs)),
+            dim_brightness: config.screen.dim_brightness,
+            auto_lock: (config.screen.auto_lock_secs > 0)
+                .then(|| Duration::from_secs(config.screen.auto_lock_secs)),
+            power_action: config.power.long_press,
+        })
+    }
+
+    pub fn screen(&self) -> Screen {
+        Screen {
+            sender: self.sender.clone(),
+            brightness_changed: self.brightness_changed.clone(),
+        }
+    }
+
+    fn set_backlight(&self, percent: u8) {
+        if let Some(ref backlight) = self.backlight {
+            if let Err(e) = backlight.set(percent) {
+                warn!("Failed to set brightness: {}", e);
+            }
+        }
+    }
+
+    /// Changes the user's brightness level by `delta` percent and remembers it.
+    fn step_brightness(&self, delta: i16) {
+        let mut state = self.state.borrow_mut();
+        state.brightness =
+            (state.brightness as i16 + delta).clamp(MIN_BRIGHTNESS as i16, 100) as u8;
+        debug!("brightness: {}", state.brightness);
+        self.set_backlight(state.brightness);
+        if let Err(e) = state.save() {
+            warn!("Failed to save state: {}", e);
         }
     }
 }
@@ -71,69 +184,199 @@ impl Platform for MyPlatform {
         Ok(self.window.clone())
     }
 
+    fn new_event_loop_proxy(&self) -> Option<Box<dyn EventLoopProxy>> {
+        Some(Box::new(Proxy {
+            sender: self.sender.clone(),
+        }))
+    }
+
     fn run_event_loop(&self) -> Result<(), slint::PlatformError> {
         let mut evdev = self.evdev.take().unwrap();
-        let (input_tx, input_rx) = channel();
+        let input_tx = self.sender.clone();
         thread::spawn(move || loop {
-            if let Some(event) = evdev.fetch_events() {
-                let _ = input_tx.send(event);
+            // Blocks until the next batch of input events arrives.
+            let events = match evdev.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Failed to read input events: {}", e);
+                    break;
+                }
+            };
+            for event in events {
+                if input_tx.send(Event::Input(event)).is_err() {
+                    return;
+                }
             }
         });
 
-        let mut framebuffer = self.framebuffer.borrow_mut();
-        let mut frame: Vec<RGBX8> = vec![
-            RGBX8::default();
-            self.window.size().width as usize
-                * self.window.size().height as usize
-        ];
-        loop {
+        let mut display = self.display.borrow_mut();
+        let mut frame = Frame::new(display.format, display.width * display.height);
+        let mut screen_lock = ScreenLock::new(self.auto_lock);
+        let mut dimmed = false;
+        self.set_backlight(self.state.borrow().brightness);
+        'event_loop: loop {
             // Let Slint run the timer hooks and update animations.
             slint::platform::update_timers_and_animations();
 
-            while let Ok(event) = input_rx.try_recv() {
-                debug!("input event: {:?}", &event);
-                self.window.dispatch_event(event);
+            // Draw the scene if something needs to be drawn. Nothing is drawn while locked.
+            if !screen_lock.is_locked() {
+                self.window.draw_if_needed(|renderer| {
+                    let dirty = frame.render(renderer, display.width);
+                    display.present(&frame, &dirty);
+                });
             }
 
-            // Draw the scene if something needs to be drawn.
-            self.window.draw_if_needed(|renderer| {
-                renderer.render(&mut frame, self.window.size().width as usize);
-                framebuffer.frame[self.buffer_offset..self.buffer_offset + self.buffer_size]
-                    .copy_from_slice(&frame.as_bytes());
-            });
-        }
-    }
-}
+            // Sleep until the next timer fires, an input event arrives, the screen should dim or
+            // auto-lock, or another thread wakes us up through the event loop proxy.
+            let timeout = if !screen_lock.is_locked()
+                && self.window.window().has_active_animations()
+            {
+                Some(ANIMATION_FRAME_TIME)
+            } else {
+                [
+                    slint::platform::duration_until_next_timer_update(),
+                    screen_lock.time_until_auto_lock(),
+                    screen_lock.time_until_power_hold(),
+                    self.dim_after
+                        .filter(|_| !dimmed && !screen_lock.is_locked())
+                        .map(|dim_after| dim_after.saturating_sub(screen_lock.idle_time())),
+                ]
+                .into_iter()
+                .flatten()
+                .min()
+            };
+            let event = match timeout {
+                Some(timeout) => match self.receiver.recv_timeout(timeout) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => break 'event_loop,
+                },
+                None => match self.receiver.recv() {
+                    Ok(event) => Some(event),
+                    Err(_) => break 'event_loop,
+                },
+            };
 
-#[derive(Debug, Copy, Clone, Default)]
-struct RGBX8([u8; 4]);
+            for event in event.into_iter().chain(self.receiver.try_iter()) {
+                match event {
+                    Event::Input(event) => {
+                        debug!("input event: {:?}", &event);
+                        if dimmed && !screen_lock.is_locked() {
+                            self.set_backlight(self.state.borrow().brightness);
+                            dimmed = false;
+                        }
+                        match screen_lock.handle(event) {
+                            LockEvent::Forward => {
+                                // Menu + Up/Down adjusts the brightness.
+                                if event.state != KeyState::Released
+                                    && matches!(event.key, Key::Up | Key::Down)
+                                    && screen_lock.is_held(Key::Menu)
+                                {
+                                    if event.key == Key::Up {
+                                        self.step_brightness(BRIGHTNESS_STEP as i16);
+                                    } else {
+                                        self.step_brightness(-(BRIGHTNESS_STEP as i16));
+                                    }
+                                    // Menu on its own does something else in the UI.
+                                    screen_lock.swallow(Key::Menu);
+                                    if let Some(f) = self.brightness_changed.borrow().as_ref() {
+                                        f();
+                                    }
+                                } else {
+                                    for event in
+                                        screen_lock.take_pending().into_iter().chain([event])
+                                    {
+                                        if let Some(event) = Option::<WindowEvent>::from(event) {
+                                            self.window.dispatch_event(event);
+                                        }
+                                    }
+                                }
+                            }
+                            LockEvent::Tap => {
+                                for state in [KeyState::Pressed, KeyState::Released] {
+                                    let event = KeyEvent { state, ..event };
+                                    if let Some(event) = Option::<WindowEvent>::from(event) {
+                                        self.window.dispatch_event(event);
+                                    }
+                                }
+                            }
+                            LockEvent::Swallow => {}
+                            LockEvent::Lock => {
+                                display.blank(true);
+                                self.set_backlight(0);
+                            }
+                            LockEvent::Unlock => {
+                                display.blank(false);
+                                self.set_backlight(self.state.borrow().brightness);
+                                dimmed = false;
+                                self.window.request_redraw();
+                            }
+                        }
+                    }
+                    Event::Invoke(f) => f(),
+                    Event::Lock => {
+                        if !screen_lock.is_locked() {
+                            screen_lock.lock();
+                            display.blank(true);
+                            self.set_backlight(0);
+                        }
+                    }
+                    Event::Brightness(percent) => {
+                        if !screen_lock.is_locked() {
+                            self.set_backlight(percent);
+                            dimmed = false;
+                        }
+                    }
+                    Event::Quit => break 'event_loop,
+                }
+            }
 
-impl TargetPixel for RGBX8 {
-    fn blend(&mut self, color: PremultipliedRgbaColor) {
-        let a = (u8::MAX - color.alpha) as u16;
-        self.0[0] = (self.0[0] as u16 * a / 255) as u8 + color.blue;
-        self.0[1] = (self.0[1] as u16 * a / 255) as u8 + color.green;
-        self.0[2] = (self.0[2] as u16 * a / 255) as u8 + color.red;
-    }
+            if screen_lock.take_power_hold() {
+                match self.power_action {
+                    PowerAction::Quit => {
+                        info!("power held, quitting");
+                        break 'event_loop;
+                    }
+                    PowerAction::Suspend => {
+                        info!("power held, suspending");
+                        display.blank(true);
+                        self.set_backlight(0);
+                        if let Err(e) = fs::write("/sys/power/state", "mem") {
+                            warn!("Failed to suspend: {}", e);
+                        }
+                        // We only get here once the device wakes up again.
+                        screen_lock.touch();
+                        if !screen_lock.is_locked() {
+                            display.blank(false);
+                            self.set_backlight(self.state.borrow().brightness);
+                            dimmed = false;
+                            self.window.request_redraw();
+                        }
+                    }
+                }
+            }
 
-    fn from_rgb(r: u8, g: u8, b: u8) -> Self {
-        Self([b, g, r, 255])
-    }
-}
+            if screen_lock.time_until_auto_lock() == Some(Duration::ZERO) {
+                screen_lock.lock();
+                display.blank(true);
+                self.set_backlight(0);
+            } else if !dimmed
+                && !screen_lock.is_locked()
+                && self
+                    .dim_after
+                    .is_some_and(|dim_after| screen_lock.idle_time() >= dim_after)
+            {
+                debug!("dim screen");
+                self.set_backlight(self.dim_brightness.min(self.state.borrow().brightness));
+                dimmed = true;
+            }
+        }
 
-trait AsBytes {
-    fn as_bytes(&self) -> &[u8];
-}
+        // Leave a blank, lit screen behind for whatever frontend launched us.
+        display.clear();
+        display.blank(false);
+        self.set_backlight(self.state.borrow().brightness);
 
-impl AsBytes for [RGBX8] {
-    /// The components interpreted as raw bytes, in machine's native endian. In `RGB` bytes of the red component are first.
-    #[inline]
-    fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(
-                self.as_ptr() as *const _,
-                self.len() * core::mem::size_of::<RGBX8>(),
-            )
-        }
+        Ok(())
     }
 }
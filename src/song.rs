@@ -13,8 +13,9 @@ use lofty::{
     picture::PictureType,
     tag::Accessor,
 };
-use slint::SharedPixelBuffer;
+use slint::{ModelRc, SharedPixelBuffer, VecModel};
 
+use crate::chapters::{self, Chapter};
 use crate::Song;
 
 pub struct SongData {
@@ -24,6 +25,7 @@ pub struct SongData {
     pub album: Option<String>,
     pub cover_art: Option<RgbaImage>,
     pub duration: Duration,
+    pub chapters: Vec<Chapter>,
 }
 
 impl SongData {
@@ -50,6 +52,8 @@ impl SongData {
             .transpose()?
             .map(|image| image.to_rgba8());
 
+        let chapters = chapters::read(file, Some(tag));
+
         Ok(Self {
             path,
             title,
@@ -57,6 +61,7 @@ impl SongData {
             album,
             cover_art,
             duration,
+            chapters,
         })
     }
 
@@ -88,6 +93,15 @@ impl From<&SongData> for Song {
             duration: song.duration.as_secs() as i32,
             path: song.path.to_string_lossy().as_ref().into(),
             title: song.title.as_deref().unwrap_or_default().into(),
+            chapters: ModelRc::new(VecModel::from(
+                song.chapters
+                    .iter()
+                    .map(|chapter| crate::Chapter {
+                        title: chapter.title.as_str().into(),
+                        start_ms: chapter.start.as_millis() as i32,
+                    })
+                    .collect::<Vec<_>>(),
+            )),
         }
     }
 }
@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Lowest brightness the user can pick, so the screen never goes fully dark by accident.
+pub const MIN_BRIGHTNESS: u8 = 10;
+/// Change of brightness per key press, in percent.
+pub const BRIGHTNESS_STEP: u8 = 10;
+
+/// State that is remembered between runs, saved to `state.toml` whenever it changes.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Backlight brightness, in percent.
+    pub brightness: u8,
+    /// The song that was playing, to resume on the next start.
+    pub song: Option<PathBuf>,
+    /// Position in `song`, in seconds.
+    pub progress: i32,
+    /// Whether crossfeed was toggled on or off in the player, overriding the config.
+    pub crossfeed: Option<bool>,
+    pub equalizer: EqState,
+    /// Playback speed per album, or per file for files without one. Only speeds other than 1
+    /// are kept.
+    pub speeds: BTreeMap<String, f32>,
+    /// Position in seconds in each audiobook that was left unfinished, by path.
+    pub bookmarks: BTreeMap<String, i32>,
+}
+
+/// The equalizer as last set on the EQ screen.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqState {
+    /// Name of the chosen preset.
+    pub preset: String,
+    /// Gain of each band of the preset, in dB, as adjusted on the EQ screen.
+    pub gains: Vec<f32>,
+    /// Gain of the bass shelf, in dB.
+    pub bass: f32,
+    /// Gain of the treble shelf, in dB.
+    pub treble: f32,
+}
+
+impl Default for EqState {
+    fn default() -> Self {
+        Self {
+            preset: "Flat".to_string(),
+            gains: vec![],
+            bass: 0.0,
+            treble: 0.0,
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            brightness: 50,
+            song: None,
+            progress: 0,
+            crossfeed: None,
+            equalizer: EqState::default(),
+            speeds: BTreeMap::new(),
+            bookmarks: BTreeMap::new(),
+        }
+    }
+}
+
+impl State {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut state = if path.exists() {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            toml::from_str(&text).unwrap_or_else(|e| {
+                warn!("Failed to parse {}, starting fresh: {}", path.display(), e);
+                Self::default()
+            })
+        } else {
+            info!("no state at {}, starting fresh", path.display());
+            Self::default()
+        };
+        state.path = path.to_path_buf();
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let text = toml::to_string(self)?;
+        // Write to a temporary file first so a crash mid-write can't corrupt the state.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, text).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}